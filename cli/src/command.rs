@@ -26,7 +26,7 @@ use std::str::FromStr;
 
 use amplify::confinement::{SmallOrdMap, TinyOrdMap, TinyOrdSet, U16 as MAX16};
 use baid64::DisplayBaid64;
-use bpstd::{Sats, XpubDerivable};
+use bpstd::{Address, Sats, Txid, XpubDerivable};
 use bpwallet::cli::{BpCommand, Config, Exec};
 use bpwallet::Wallet;
 use ifaces::{IfaceStandard, Rgb20, Rgb21, Rgb25};
@@ -43,10 +43,11 @@ use rgb::validation::Validity;
 use rgb::vm::RgbIsa;
 use rgb::{
     BundleId, ContractId, DescriptorRgb, GenesisSeal, GraphSeal, Identity, OutputSeal, RgbDescr,
-    RgbKeychain, StateType, StoredWallet, TransferParams, WalletError, WalletProvider, XChain,
-    XOutpoint, XOutputSeal,
+    StateType, StoredWallet, TapretTweaks, TransferParams, TxStatus, WalletError, WalletProvider,
+    XChain, XOutpoint, XOutputSeal,
 };
 use rgbstd::interface::OutpointFilter;
+use seals::txout::CloseMethod;
 use serde_crate::{Deserialize, Serialize};
 use strict_types::encoding::{FieldName, TypeName};
 use strict_types::StrictVal;
@@ -192,6 +193,11 @@ pub enum Command {
         /// Fee
         fee: Sats,
 
+        /// Method for closing seals defined by the transfer (`opret1st` or
+        /// `tapret1st`). Defaults to the method used by the wallet descriptor.
+        #[clap(long)]
+        method: Option<CloseMethod>,
+
         /// Name of PSBT file to save. If not given, prints PSBT to STDOUT
         psbt: Option<PathBuf>,
     },
@@ -230,6 +236,11 @@ pub enum Command {
         #[clap(short, long, default_value = "400")]
         fee: Sats,
 
+        /// Method for closing seals defined by the transfer (`opret1st` or
+        /// `tapret1st`). Defaults to the method used by the wallet descriptor.
+        #[clap(long)]
+        method: Option<CloseMethod>,
+
         /// File for generated transfer consignment
         consignment: PathBuf,
 
@@ -237,6 +248,74 @@ pub enum Command {
         psbt: Option<PathBuf>,
     },
 
+    /// Update the stash with the signed and finalized witness transaction of
+    /// a transfer created with `transfer` or `consign` commands
+    #[display("finalize-transfer")]
+    FinalizeTransfer {
+        /// Name of PSBT file containing signed and finalized transfer
+        psbt: PathBuf,
+    },
+
+    /// Re-resolve mining status of the wallet witness transactions, detecting
+    /// re-orgs and new confirmations
+    #[display("sync")]
+    Sync {
+        /// Number of confirmations after which witness mining status is
+        /// considered final and is not checked anymore
+        #[arg(short, long, default_value = "6")]
+        depth: u32,
+    },
+
+    /// Abandon transfer which witness transaction is not going to be
+    /// broadcast, removing tapret tweak it has added to the wallet and the
+    /// allocations it has created from the contract state
+    #[display("abandon")]
+    Abandon {
+        /// Abandon the transfer even if its witness transaction is known to
+        /// the resolver
+        #[arg(short, long)]
+        force: bool,
+
+        /// Witness transaction id of the transfer
+        txid: Txid,
+    },
+
+    /// Print wallet descriptor in a textual form, including tapret tweaks, such
+    /// that it can be backed up and used with `--descr` argument
+    #[display("descriptor")]
+    Descriptor,
+
+    /// List tapret tweaks of the wallet outputs together with the addresses
+    /// they produce
+    #[display("tweaks")]
+    Tweaks,
+
+    /// Export tapret tweaks of the wallet for a backup. Without the tweaks
+    /// outputs created by the wallet transfers can't be spent
+    #[display("export-tweaks")]
+    ExportTweaks {
+        /// File to save the tweaks to. If not given, prints the tweaks to
+        /// STDOUT
+        file: Option<PathBuf>,
+    },
+
+    /// Import tapret tweaks exported with `export-tweaks` command into the
+    /// wallet
+    #[display("import-tweaks")]
+    ImportTweaks {
+        /// File with the exported tweaks
+        file: PathBuf,
+    },
+
+    /// Recover tapret tweaks missing in the wallet from the tapret anchors
+    /// known to the stash
+    #[display("recover-tweaks")]
+    RecoverTweaks {
+        /// Maximal index of the tapret keychain to look the tweaked outputs at
+        #[arg(long, default_value = "1000")]
+        max_index: u16,
+    },
+
     /// Inspects any RGB data file
     #[display("inspect")]
     Inspect {
@@ -274,6 +353,20 @@ pub enum Command {
         root_dir: String,
     },
 
+    /// Export witness transactions of a consignment for validating it on a
+    /// machine without network access with `--witness-bundle` argument.
+    ///
+    /// With `--spv` the block header chain is exported as well, such that the
+    /// bundle can be used together with `--spv` on the offline machine
+    #[display("export-witnesses")]
+    ExportWitnesses {
+        /// File with the contract or transfer consignment
+        consignment: PathBuf,
+
+        /// File to save witness bundle to
+        bundle: PathBuf,
+    },
+
     /// Validate transfer consignment
     #[display("validate")]
     Validate {
@@ -284,9 +377,12 @@ pub enum Command {
     /// Validate transfer consignment & accept to the stash
     #[display("accept")]
     Accept {
-        /// Force accepting consignments with non-mined terminal witness
+        /// Minimal number of confirmations terminal witness transactions must
+        /// have; zero requires them to be at least in the mempool. If not
+        /// given, consignments are accepted whatever the status of their
+        /// terminal witnesses is
         #[arg(short, long)]
-        force: bool,
+        min_confirmations: Option<u32>,
 
         /// File with the transfer consignment
         file: PathBuf,
@@ -437,10 +533,11 @@ impl Exec for RgbArgs {
                     UniversalFile::Contract(contract) => {
                         let id = contract.consignment_id();
                         eprintln!("Importing consignment {id}:");
+                        let chain = self.chain_params()?;
                         let mut resolver = self.resolver()?;
                         eprint!("- validating the contract {} ... ", contract.contract_id());
                         let contract = contract
-                            .validate(&mut resolver, self.general.network.is_testnet())
+                            .validate(&mut resolver, chain.testnet)
                             .map_err(|(status, _)| {
                                 eprintln!("failure");
                                 status.to_string()
@@ -728,11 +825,10 @@ impl Exec for RgbArgs {
                 let mut wallet = self.rgb_wallet(&config)?;
                 let iface = TypeName::try_from(iface.to_owned()).expect("invalid interface name");
 
+                let keychains = wallet.wallet().seal_keychains();
                 let outpoint = wallet
                     .wallet()
-                    .coinselect(Sats::ZERO, |utxo| {
-                        RgbKeychain::contains_rgb(utxo.terminal.keychain)
-                    })
+                    .coinselect(Sats::ZERO, |utxo| keychains.contains(utxo.terminal.keychain))
                     .next();
                 let network = wallet.wallet().network();
                 let beneficiary = match (address_based, outpoint) {
@@ -744,7 +840,7 @@ impl Exec for RgbArgs {
                     (true, _) => {
                         let addr = wallet
                             .wallet()
-                            .addresses(RgbKeychain::Rgb)
+                            .addresses(keychains.opret)
                             .next()
                             .expect("no addresses left")
                             .addr;
@@ -775,11 +871,13 @@ impl Exec for RgbArgs {
                 invoice,
                 fee,
                 sats,
+                method,
                 psbt: psbt_file,
             } => {
                 let mut wallet = self.rgb_wallet(&config)?;
                 // TODO: Support lock time and RBFs
-                let params = TransferParams::with(*fee, *sats);
+                let mut params = TransferParams::with(*fee, *sats);
+                params.close_method = *method;
 
                 let (psbt, _) = wallet
                     .construct_psbt(invoice, params)
@@ -817,12 +915,14 @@ impl Exec for RgbArgs {
                 invoice,
                 fee,
                 sats,
+                method,
                 psbt: psbt_file,
                 consignment: out_file,
             } => {
                 let mut wallet = self.rgb_wallet(&config)?;
                 // TODO: Support lock time and RBFs
-                let params = TransferParams::with(*fee, *sats);
+                let mut params = TransferParams::with(*fee, *sats);
+                params.close_method = *method;
 
                 let (psbt, _, transfer) =
                     wallet.pay(invoice, params).map_err(|err| err.to_string())?;
@@ -841,6 +941,117 @@ impl Exec for RgbArgs {
                     },
                 }
             }
+            Command::FinalizeTransfer { psbt: psbt_name } => {
+                let mut wallet = self.rgb_wallet(&config)?;
+                let mut psbt_file = File::open(psbt_name)?;
+                let psbt = Psbt::decode(&mut psbt_file)?;
+                wallet
+                    .finalize_transfer(&psbt)
+                    .map_err(|err| err.to_string())?;
+                eprintln!("Witness transaction {} is updated in the stash", psbt.txid());
+            }
+            Command::Sync { depth } => {
+                let mut wallet = self.rgb_wallet(&config)?;
+                let mut resolver = self.resolver()?;
+                let tip_height = resolver
+                    .resolve_tip_height()
+                    .map_err(WalletError::Resolver)?;
+                let final_height = (tip_height + 1).saturating_sub(*depth);
+                let report = wallet.sync_witnesses(&mut resolver, final_height)?;
+                for (txid, pos) in &report.confirmed {
+                    println!("{txid}\tconfirmed at height {}", pos.height());
+                }
+                for txid in &report.reorged {
+                    println!("{txid}\tre-orged out of the blockchain");
+                }
+                for (txid, err) in &report.failed {
+                    eprintln!("{txid}\tunable to resolve: {err}");
+                }
+                for outpoint in report.confirmed_allocations(wallet.wallet().outpoints()) {
+                    println!("Allocation at {outpoint} is confirmed");
+                }
+                for outpoint in report.invalid_allocations(wallet.wallet().outpoints()) {
+                    println!("Allocation at {outpoint} is invalid until its witness is re-mined");
+                }
+                if report.is_empty() {
+                    eprintln!("Witness status is up to date");
+                }
+                for txid in wallet
+                    .confirm_tapret_tweaks(&mut resolver)
+                    .map_err(|e| WalletError::Resolver(e.into()))?
+                {
+                    println!("{txid}\ttapret tweak is confirmed");
+                }
+            }
+            Command::Abandon { force, txid } => {
+                let mut wallet = self.rgb_wallet(&config)?;
+                let mut resolver = self.resolver()?;
+                if !*force {
+                    let status = resolver
+                        .resolve_status(*txid)
+                        .map_err(WalletError::Resolver)?;
+                    if status != TxStatus::Unknown {
+                        return Err(WalletError::Custom(format!(
+                            "witness transaction {txid} is already known to the network \
+                             ({status}); use --force to abandon it anyway"
+                        )));
+                    }
+                }
+                match wallet.abandon(*txid, &mut resolver)? {
+                    Some((terminal, tweak)) => {
+                        eprintln!("Tapret tweak {tweak} of {terminal} is removed from the wallet")
+                    }
+                    None => eprintln!("Transfer {txid} has no pending tapret tweak"),
+                }
+            }
+            Command::Descriptor => {
+                let wallet = self.rgb_wallet(&config)?;
+                let descriptor: &RgbDescr = wallet.wallet();
+                println!("{descriptor}");
+            }
+            Command::Tweaks => {
+                let wallet = self.rgb_wallet(&config)?;
+                let descriptor: &RgbDescr = wallet.wallet();
+                let network = wallet.wallet().network();
+                for terminal in descriptor.tapret_terminals() {
+                    let tweaks = descriptor.tapret_tweaks(terminal);
+                    let scripts = descriptor.derive_tapret_tweaked(terminal);
+                    for (tweak, script) in tweaks.iter().zip(scripts) {
+                        let address = Address::with(&script.to_script_pubkey(), network)
+                            .expect("taproot scripts always have an address");
+                        println!("{terminal}\t{tweak}\t{address}");
+                    }
+                }
+            }
+            Command::ExportTweaks { file } => {
+                let wallet = self.rgb_wallet(&config)?;
+                let descriptor: &RgbDescr = wallet.wallet();
+                let tweaks = TapretTweaks::export(descriptor);
+                match file {
+                    Some(file) => fs::write(file, tweaks.to_string())?,
+                    None => print!("{tweaks}"),
+                }
+            }
+            Command::ImportTweaks { file } => {
+                let mut wallet = self.rgb_wallet(&config)?;
+                let tweaks = TapretTweaks::from_str(&fs::read_to_string(file)?)
+                    .map_err(|e| WalletError::Custom(e.to_string()))?;
+                let count = tweaks
+                    .import_into(wallet.wallet_mut().descriptor_mut())
+                    .map_err(|e| WalletError::Custom(e.to_string()))?;
+                eprintln!("{count} new tapret tweak(s) imported");
+            }
+            Command::RecoverTweaks { max_index } => {
+                let mut wallet = self.rgb_wallet(&config)?;
+                let resolver = self.resolver()?;
+                let recovered = wallet
+                    .recover_tapret_tweaks(&resolver, *max_index)
+                    .map_err(WalletError::Custom)?;
+                for (terminal, tweak) in &recovered {
+                    println!("{terminal}\t{tweak}");
+                }
+                eprintln!("{} tapret tweak(s) recovered", recovered.len());
+            }
             Command::Inspect { file, dir, path } => {
                 #[derive(Clone, Debug)]
                 #[derive(Serialize, Deserialize)]
@@ -1042,29 +1253,87 @@ impl Exec for RgbArgs {
                 )?;
                 eprintln!("Dump is successfully generated and saved to '{root_dir}'");
             }
+            Command::ExportWitnesses {
+                consignment,
+                bundle: bundle_file,
+            } => {
+                let mut resolver = self.resolver()?;
+                let chain = self.chain_params()?;
+                let mut bundle = match UniversalFile::load_file(consignment)? {
+                    UniversalFile::Contract(contract) => {
+                        resolver.export_witnesses(chain.clone(), &contract)
+                    }
+                    UniversalFile::Transfer(transfer) => {
+                        resolver.export_witnesses(chain.clone(), &transfer)
+                    }
+                    UniversalFile::Kit(_) => {
+                        return Err(s!("kits do not contain witness transactions").into());
+                    }
+                }
+                .map_err(WalletError::Resolver)?;
+                if self.spv {
+                    resolver
+                        .export_headers(&chain, &mut bundle)
+                        .map_err(WalletError::Resolver)?;
+                }
+                bundle.save(bundle_file)?;
+                eprintln!(
+                    "{} witness transactions exported to '{}'",
+                    bundle.txes.len(),
+                    bundle_file.display()
+                );
+            }
             Command::Validate { file } => {
+                let chain = self.chain_params()?;
                 let mut resolver = self.resolver()?;
                 let consignment = Transfer::load_file(file)?;
                 resolver.add_terminals(&consignment);
-                let status =
-                    match consignment.validate(&mut resolver, self.general.network.is_testnet()) {
-                        Ok(consignment) => consignment.into_validation_status(),
-                        Err((status, _)) => status,
-                    };
+                resolver
+                    .resolve_consignment(&consignment)
+                    .map_err(WalletError::Resolver)?;
+                let status = match consignment.validate(&mut resolver, chain.testnet) {
+                    Ok(consignment) => consignment.into_validation_status(),
+                    Err((status, _)) => status,
+                };
                 if status.validity() == Validity::Valid {
                     eprintln!("The provided consignment is valid")
                 } else {
                     eprintln!("{status}");
                 }
             }
-            Command::Accept { force: _, file } => {
-                // TODO: Ensure we properly handle unmined terminal transactions
+            Command::Accept {
+                min_confirmations,
+                file,
+            } => {
                 let mut stock = self.rgb_stock()?;
+                let chain = self.chain_params()?;
                 let mut resolver = self.resolver()?;
                 let transfer = Transfer::load_file(file)?;
                 resolver.add_terminals(&transfer);
+                if let Some(min_confirmations) = *min_confirmations {
+                    let status = resolver.terminal_status().map_err(WalletError::Resolver)?;
+                    for (txid, status) in status {
+                        let accepted = match status {
+                            TxStatus::Unknown => false,
+                            TxStatus::Mempool => min_confirmations == 0,
+                            TxStatus::Mined { confirmations, .. } => {
+                                confirmations >= min_confirmations
+                            }
+                        };
+                        if !accepted {
+                            return Err(format!(
+                                "terminal witness transaction {txid} is {status}, while \
+                                 {min_confirmations} confirmations are required"
+                            )
+                            .into());
+                        }
+                    }
+                }
+                resolver
+                    .resolve_consignment(&transfer)
+                    .map_err(WalletError::Resolver)?;
                 let valid = transfer
-                    .validate(&mut resolver, self.general.network.is_testnet())
+                    .validate(&mut resolver, chain.testnet)
                     .map_err(|(status, _)| status)?;
                 stock.accept_transfer(valid, &mut resolver)?;
                 eprintln!("Transfer accepted into the stash");
@@ -24,12 +24,21 @@ extern crate amplify;
 
 mod rgb;
 
+use std::collections::BTreeMap;
+
+use amplify::confinement::Confined;
 use bp::dbc::opret::OpretProof;
-use bp::dbc::tapret::TapretProof;
+use bp::dbc::tapret::{TapretPathProof, TapretProof};
+use bp::dbc::{Anchor, Method};
+use bp::seals::txout::CloseMethod;
+use bp::{ScriptPubkey, Tx, TxIn, VarIntArray};
+use commit_verify::{mpc, CommitId, TryCommitVerify};
 pub use psbt::*;
 pub use rgb::*;
-use rgbstd::containers::{AnchorSet, Batch, CloseMethodSet, Fascia, PubWitness, XPubWitness};
-use rgbstd::XChain;
+use rgbstd::containers::{
+    AnchorSet, Batch, BundleDichotomy, CloseMethodSet, Fascia, PubWitness, XPubWitness,
+};
+use rgbstd::{ContractId, XChain};
 
 pub use self::rgb::{
     ProprietaryKeyRgb, RgbExt, RgbInExt, RgbOutExt, RgbPsbtError, PSBT_GLOBAL_RGB_TRANSITION,
@@ -58,9 +67,40 @@ pub enum CommitError {
     Dbc(DbcPsbtError),
 }
 
-#[derive(Clone, Eq, PartialEq, Debug, Display, Error)]
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
 #[display(doc_comments)]
-pub enum ExtractError {}
+pub enum ExtractError {
+    /// PSBT doesn't contain information about RGB contracts.
+    NoContracts,
+
+    /// PSBT doesn't have an output hosting {0} commitment required by the RGB
+    /// bundles it contains.
+    NoHostOutput(Method),
+
+    /// output hosting {0} commitment doesn't contain commitment data; probably
+    /// the PSBT was not committed yet.
+    NotCommitted(Method),
+
+    /// {0} commitment present in the PSBT doesn't match the multi-protocol
+    /// commitment reconstructed from the PSBT data.
+    CommitmentMismatch(Method),
+
+    /// bundle for contract {0} is absent from the multi-protocol commitment or
+    /// doesn't match the bundle committed with {1} method.
+    BundleMismatch(ContractId, Method),
+
+    #[from]
+    #[display(inner)]
+    Rgb(RgbPsbtError),
+
+    #[from]
+    #[display(inner)]
+    Mpc(MpcPsbtError),
+
+    #[from]
+    #[display(inner)]
+    MpcTree(mpc::Error),
+}
 
 // TODO: Batch must be homomorphic by the outpoint type (chain)
 
@@ -68,7 +108,14 @@ pub trait RgbPsbt {
     fn rgb_embed(&mut self, batch: Batch) -> Result<(), EmbedError>;
     #[allow(clippy::result_large_err)]
     fn rgb_commit(&mut self) -> Result<Fascia, CommitError>;
+    /// Extracts fascia from an already committed PSBT. If the PSBT is
+    /// finalized, the witness contains the signed transaction; otherwise the
+    /// unsigned transaction is used.
+    #[allow(clippy::result_large_err)]
     fn rgb_extract(&self) -> Result<Fascia, ExtractError>;
+    /// Constructs signed witness transaction, if all of the PSBT inputs are
+    /// finalized.
+    fn extract_signed_tx(&self) -> Option<Tx>;
 }
 
 impl RgbPsbt for Psbt {
@@ -116,7 +163,9 @@ impl RgbPsbt for Psbt {
             (None, Some(opret)) => AnchorSet::Opret(opret),
             (Some(tapret), Some(opret)) => AnchorSet::Double { tapret, opret },
         };
-        // TODO: Use signed transaction here!
+        // At this stage the transaction can't be signed yet, since the commitment
+        // changes outputs; the signed version must be provided later via
+        // `rgb_extract`
         let witness = PubWitness::with(self.to_unsigned_tx().finalize());
         Ok(Fascia {
             witness: XPubWitness::Bitcoin(witness),
@@ -126,6 +175,231 @@ impl RgbPsbt for Psbt {
     }
 
     fn rgb_extract(&self) -> Result<Fascia, ExtractError> {
-        todo!("implement RGB PSBT fascia extraction for multi-party protocols")
+        let bundles = self.rgb_bundles()?;
+        let methods = bundles
+            .values()
+            .flat_map(|b| b.iter())
+            .map(|b| CloseMethodSet::from(b.close_method))
+            .reduce(|methods, method| methods | method)
+            .ok_or(ExtractError::NoContracts)?;
+
+        let (mut tapret_anchor, mut opret_anchor) = (None, None);
+        if methods.has_tapret_first() {
+            let output = self
+                .outputs()
+                .find(|output| output.script.is_p2tr())
+                .ok_or(ExtractError::NoHostOutput(Method::TapretFirst))?;
+            let mpc_proof = output.rgb_mpc_proof(&bundles, CloseMethod::TapretFirst)?;
+            let commitment = output
+                .tapret_commitment()
+                .map_err(|_| ExtractError::NotCommitted(Method::TapretFirst))?;
+            if commitment.mpc != mpc_proof.commit_id() {
+                return Err(ExtractError::CommitmentMismatch(Method::TapretFirst));
+            }
+            let internal_pk = output
+                .tap_internal_key
+                .ok_or(ExtractError::NotCommitted(Method::TapretFirst))?;
+            let dbc_proof = TapretProof {
+                path_proof: TapretPathProof::root(commitment.nonce),
+                internal_pk,
+            };
+            tapret_anchor = Some(Anchor::new(mpc_proof, dbc_proof));
+        }
+        if methods.has_opret_first() {
+            let output = self
+                .outputs()
+                .find(|output| output.script.is_op_return())
+                .ok_or(ExtractError::NoHostOutput(Method::OpretFirst))?;
+            let mpc_proof = output.rgb_mpc_proof(&bundles, CloseMethod::OpretFirst)?;
+            if output.script != ScriptPubkey::op_return(mpc_proof.commit_id().as_slice()) {
+                return Err(ExtractError::CommitmentMismatch(Method::OpretFirst));
+            }
+            opret_anchor = Some(Anchor::new(mpc_proof, OpretProof::default()));
+        }
+        let anchor = match (tapret_anchor, opret_anchor) {
+            (None, None) => return Err(ExtractError::NoContracts),
+            (Some(tapret), None) => AnchorSet::Tapret(tapret),
+            (None, Some(opret)) => AnchorSet::Opret(opret),
+            (Some(tapret), Some(opret)) => AnchorSet::Double { tapret, opret },
+        };
+
+        let tx = self
+            .extract_signed_tx()
+            .unwrap_or_else(|| self.to_unsigned_tx().finalize());
+        let witness = PubWitness::with(tx);
+        let bundles = Confined::try_from(bundles).map_err(|_| ExtractError::NoContracts)?;
+        Ok(Fascia {
+            witness: XPubWitness::Bitcoin(witness),
+            anchor,
+            bundles,
+        })
+    }
+
+    fn extract_signed_tx(&self) -> Option<Tx> {
+        let mut tx = self.to_unsigned_tx().finalize();
+        let inputs = tx
+            .inputs
+            .iter()
+            .zip(self.inputs())
+            .map(|(txin, input)| {
+                if input.final_script_sig.is_none() && input.final_witness.is_none() {
+                    return None;
+                }
+                Some(TxIn {
+                    sig_script: input.final_script_sig.clone().unwrap_or_default(),
+                    witness: input.final_witness.clone().unwrap_or_default(),
+                    ..txin.clone()
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        tx.inputs = VarIntArray::try_from(inputs).expect("same number of inputs");
+        Some(tx)
+    }
+}
+
+trait RgbMpcExt {
+    /// Reconstructs multi-protocol commitment proof from the data stored in
+    /// the output, checking that it commits to all of the `bundles` using the
+    /// given close `method`.
+    #[allow(clippy::result_large_err)]
+    fn rgb_mpc_proof(
+        &self,
+        bundles: &BTreeMap<ContractId, BundleDichotomy>,
+        method: CloseMethod,
+    ) -> Result<mpc::MerkleBlock, ExtractError>;
+}
+
+impl RgbMpcExt for Output {
+    fn rgb_mpc_proof(
+        &self,
+        bundles: &BTreeMap<ContractId, BundleDichotomy>,
+        method: CloseMethod,
+    ) -> Result<mpc::MerkleBlock, ExtractError> {
+        let messages = self.mpc_message_map()?;
+        let entropy = self
+            .mpc_entropy()
+            .ok_or(ExtractError::NotCommitted(method))?;
+
+        for (contract_id, bundle) in bundles
+            .iter()
+            .flat_map(|(id, b)| b.iter().map(move |b| (*id, b)))
+            .filter(|(_, b)| b.close_method == method)
+        {
+            let protocol_id = mpc::ProtocolId::from(contract_id);
+            let message = mpc::Message::from(bundle.bundle_id());
+            if messages.get(&protocol_id) != Some(&message) {
+                return Err(ExtractError::BundleMismatch(contract_id, method));
+            }
+        }
+
+        let source = mpc::MultiSource {
+            messages,
+            static_entropy: Some(entropy),
+            ..default!()
+        };
+        let tree = mpc::MerkleTree::try_commit(&source)?;
+        Ok(mpc::MerkleBlock::from(tree))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::ByteArray;
+    use bp::{InternalPk, LockTime, Outpoint, Sats, SeqNo, TxOut, TxVer, Txid};
+    use rgbstd::{Operation, Transition, TransitionType};
+    use strict_encoding::StrictDumb;
+
+    use super::*;
+
+    fn transition(contract_id: ContractId) -> Transition {
+        Transition {
+            contract_id,
+            transition_type: TransitionType::with(0x1234),
+            ..Transition::strict_dumb()
+        }
+    }
+
+    fn psbt(method: CloseMethod, contracts: &[ContractId]) -> Psbt {
+        // Generator point of secp256k1
+        let internal_pk = InternalPk::from_byte_array([
+            0x79, 0xbe, 0x66, 0x7e, 0xf9, 0xdc, 0xbb, 0xac, 0x55, 0xa0, 0x62, 0x95, 0xce, 0x87,
+            0x0b, 0x07, 0x02, 0x9b, 0xfc, 0xdb, 0x2d, 0xce, 0x28, 0xd9, 0x59, 0xf2, 0x81, 0x5b,
+            0x16, 0xf8, 0x17, 0x98,
+        ])
+        .unwrap();
+        let host = match method {
+            CloseMethod::TapretFirst => ScriptPubkey::p2tr_key_only(internal_pk),
+            CloseMethod::OpretFirst => ScriptPubkey::op_return(&[]),
+        };
+        let inputs = contracts
+            .iter()
+            .enumerate()
+            .map(|(no, _)| UnsignedTxIn {
+                prev_output: Outpoint::new(Txid::from_byte_array([0xAB; 32]), no as u32),
+                sequence: SeqNo::from_consensus_u32(0),
+            })
+            .collect::<Vec<_>>();
+        let mut psbt = Psbt::from_tx(UnsignedTx {
+            version: TxVer::V2,
+            inputs: VarIntArray::try_from(inputs).unwrap(),
+            outputs: VarIntArray::try_from(vec![TxOut::new(host, Sats::ZERO)]).unwrap(),
+            lock_time: LockTime::ZERO,
+        });
+        let output = psbt.output_mut(0).unwrap();
+        match method {
+            CloseMethod::TapretFirst => {
+                output.tap_internal_key = Some(internal_pk);
+                output.set_tapret_host().unwrap();
+            }
+            CloseMethod::OpretFirst => {
+                output.set_opret_host().unwrap();
+            }
+        }
+        for (no, contract_id) in contracts.iter().enumerate() {
+            let transition = transition(*contract_id);
+            psbt.input_mut(no)
+                .unwrap()
+                .set_rgb_consumer(*contract_id, transition.id())
+                .unwrap();
+            psbt.push_rgb_transition(transition, method).unwrap();
+        }
+        psbt.complete_construction();
+        psbt
+    }
+
+    fn round_trip(method: CloseMethod) {
+        let contracts = [ContractId::from_byte_array([1; 32]), ContractId::from_byte_array([2; 32])];
+        let mut psbt = psbt(method, &contracts);
+        assert_eq!(psbt.rgb_extract().unwrap_err(), ExtractError::NotCommitted(method));
+
+        let committed = psbt.rgb_commit().unwrap();
+        let extracted = psbt.rgb_extract().unwrap();
+        assert_eq!(extracted, committed);
+        assert_eq!(extracted.bundles.keys().copied().collect::<Vec<_>>(), contracts);
+
+        // Signing the transaction must not affect the extracted anchor
+        for input in psbt.inputs_mut() {
+            input.final_witness = Some(default!());
+        }
+        let signed = psbt.rgb_extract().unwrap();
+        assert_eq!(signed.anchor, committed.anchor);
+        assert_eq!(signed.bundles, committed.bundles);
+    }
+
+    #[test]
+    fn opret_round_trip() { round_trip(CloseMethod::OpretFirst) }
+
+    #[test]
+    fn tapret_round_trip() { round_trip(CloseMethod::TapretFirst) }
+
+    #[test]
+    fn extract_detects_tampering() {
+        let mut psbt = psbt(CloseMethod::OpretFirst, &[ContractId::from_byte_array([1; 32])]);
+        psbt.rgb_commit().unwrap();
+        psbt.output_mut(0).unwrap().script = ScriptPubkey::op_return(&[0u8; 32]);
+        assert_eq!(
+            psbt.rgb_extract().unwrap_err(),
+            ExtractError::CommitmentMismatch(Method::OpretFirst)
+        );
     }
 }
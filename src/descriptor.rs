@@ -27,26 +27,75 @@ use amplify::Wrapper;
 use bp::dbc::tapret::TapretCommitment;
 use bp::dbc::Method;
 use bp::seals::txout::CloseMethod;
+use bpstd::secp256k1::{PublicKey, Scalar, SECP256K1};
 use bpstd::{
-    CompressedPk, Derive, DeriveCompr, DeriveSet, DeriveXOnly, DerivedScript, Idx, IdxBase,
-    IndexError, IndexParseError, KeyOrigin, Keychain, NormalIndex, TapDerivation, TapScript,
-    TapTree, Terminal, XOnlyPk, XpubDerivable, XpubSpec,
+    CompressedPk, Derive, DeriveCompr, DeriveSet, DeriveXOnly, DerivedScript, IdxBase,
+    IndexParseError, InternalPk, KeyOrigin, Keychain, NormalIndex, RedeemScript, ScriptPubkey,
+    TapDerivation, TapScript, TapTree, Terminal, Txid, WPubkeyHash, WitnessScript,
+    XOnlyPk, XpubDerivable, XpubSpec,
 };
 use commit_verify::CommitVerify;
 use descriptors::{Descriptor, SpkClass, StdDescr, TrKey, Wpkh};
 use indexmap::IndexMap;
+use sha2::{Digest, Sha256};
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
-#[display("terminal derivation {0} already has a taptweak assigned")]
-pub struct TapTweakAlreadyAssigned(pub Terminal);
+#[display("descriptor of {0:?} class can't have tapret tweaks")]
+pub struct TapretUnsupported(pub SpkClass);
 
 pub trait DescriptorRgb<K = XpubDerivable, V = ()>: Descriptor<K, V> {
     fn seal_close_method(&self) -> CloseMethod;
+    /// Keychains used for the outputs which may host RGB seals.
+    fn seal_keychains(&self) -> SealKeychains;
+    /// Adds tapret tweak to the terminal derivation. A terminal may have
+    /// multiple tweaks (for instance when an address is reused or a transfer
+    /// is re-created with a different fee); adding an already known tweak is a
+    /// no-op. Fails for descriptors which are not taproot.
     fn add_tapret_tweak(
         &mut self,
         terminal: Terminal,
         tweak: TapretCommitment,
-    ) -> Result<(), TapTweakAlreadyAssigned>;
+    ) -> Result<(), TapretUnsupported>;
+    /// Adds tapret tweak made by a witness transaction which is not known to be
+    /// mined yet. The tweak is used in derivation the same way as the rest of
+    /// the tweaks, but it can be removed with
+    /// [`DescriptorRgb::abandon_tapret_tweak`] until it gets confirmed.
+    fn add_pending_tapret_tweak(
+        &mut self,
+        witness: Txid,
+        terminal: Terminal,
+        tweak: TapretCommitment,
+    ) -> Result<(), TapretUnsupported>;
+    /// Lists witness transactions which have made tapret tweaks not yet
+    /// confirmed.
+    fn pending_tapret_witnesses(&self) -> Vec<Txid>;
+    /// Marks tapret tweak made by the witness transaction as confirmed, such
+    /// that it can't be abandoned anymore. Pending tweaks of other witnesses
+    /// with the same tweak (i.e. conflicting transactions re-created with a
+    /// different fee) are confirmed as well.
+    ///
+    /// Returns `false` if the witness has no pending tweak.
+    fn confirm_tapret_tweak(&mut self, witness: Txid) -> bool;
+    /// Removes tapret tweak made by the witness transaction which is not
+    /// confirmed, returning the removed tweak. The tweak remains known if it
+    /// is used by some other pending witness.
+    fn abandon_tapret_tweak(&mut self, witness: Txid) -> Option<(Terminal, TapretCommitment)>;
+    /// Lists terminal derivations which have tapret tweaks assigned.
+    fn tapret_terminals(&self) -> Vec<Terminal>;
+    /// Lists tapret tweaks assigned to the terminal derivation, in the order
+    /// they were added.
+    fn tapret_tweaks(&self, terminal: Terminal) -> &[TapretCommitment];
+    /// Derives scripts for each of the tapret tweaks assigned to the terminal
+    /// derivation, in the order the tweaks were added.
+    fn derive_tapret_tweaked(&self, terminal: Terminal) -> Vec<DerivedScript>;
+    /// Derives script for the terminal with the given tapret tweak, even if
+    /// the tweak is not known to the descriptor. Returns `None` for the
+    /// descriptors which can't have tapret tweaks.
+    fn derive_with_tapret(
+        &self,
+        terminal: Terminal,
+        tweak: &TapretCommitment,
+    ) -> Option<DerivedScript>;
 }
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
@@ -73,6 +122,8 @@ pub enum RgbKeychain {
 impl RgbKeychain {
     pub const RGB_ALL: [RgbKeychain; 2] = [RgbKeychain::Rgb, RgbKeychain::Tapret];
 
+    /// Checks whether the keychain is one of the default seal keychains; for
+    /// descriptors with custom keychains use [`SealKeychains::contains`].
     pub fn contains_rgb(keychain: impl Into<Keychain>) -> bool {
         let k = keychain.into().into_inner();
         k == Self::Rgb as u8 || k == Self::Tapret as u8
@@ -87,20 +138,27 @@ impl RgbKeychain {
     }
 }
 
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum KeychainParseError {
+    #[from]
+    #[display(inner)]
+    Index(IndexParseError),
+
+    /// non-standard keychain {0}; only keychains 0, 1, 9 and 10 are allowed.
+    NonStandard(u32),
+}
+
 impl FromStr for RgbKeychain {
-    type Err = IndexParseError;
+    type Err = KeychainParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match NormalIndex::from_str(s)? {
-            NormalIndex::ZERO => Ok(RgbKeychain::External),
-            NormalIndex::ONE => Ok(RgbKeychain::Internal),
-            val => Err(IndexError {
-                what: "non-standard keychain",
-                invalid: val.index(),
-                start: 0,
-                end: 1,
-            }
-            .into()),
+        match NormalIndex::from_str(s)?.index() {
+            0 => Ok(RgbKeychain::External),
+            1 => Ok(RgbKeychain::Internal),
+            9 => Ok(RgbKeychain::Rgb),
+            10 => Ok(RgbKeychain::Tapret),
+            invalid => Err(KeychainParseError::NonStandard(invalid)),
         }
     }
 }
@@ -109,6 +167,159 @@ impl From<RgbKeychain> for Keychain {
     fn from(keychain: RgbKeychain) -> Self { Keychain::from(keychain as u8) }
 }
 
+/// Keychains used by a descriptor for the outputs which may host RGB seals.
+///
+/// By default these are [`RgbKeychain::Rgb`] and [`RgbKeychain::Tapret`];
+/// wallets integrating with other software may use different keychains.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum InvalidSealKeychains {
+    /// keychain {0} is reserved for non-RGB outputs and can't be used for
+    /// seals.
+    Reserved(u8),
+
+    /// opret and tapret seals can't share the same keychain {0}.
+    Shared(u8),
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct SealKeychains {
+    /// Keychain for the outputs which are never tweaked, used by opret seals.
+    pub opret: Keychain,
+    /// Keychain for the outputs which may be tweaked with tapret commitments.
+    pub tapret: Keychain,
+}
+
+impl Default for SealKeychains {
+    fn default() -> Self {
+        SealKeychains {
+            opret: RgbKeychain::Rgb.into(),
+            tapret: RgbKeychain::Tapret.into(),
+        }
+    }
+}
+
+impl SealKeychains {
+    /// Constructs seal keychains, checking that they are distinct and don't
+    /// overlap with the external and internal (change) keychains.
+    pub fn new(
+        opret: impl Into<Keychain>,
+        tapret: impl Into<Keychain>,
+    ) -> Result<Self, InvalidSealKeychains> {
+        let opret = opret.into();
+        let tapret = tapret.into();
+        for keychain in [opret, tapret] {
+            if keychain == Keychain::OUTER || keychain == Keychain::INNER {
+                return Err(InvalidSealKeychains::Reserved(keychain.into_inner()));
+            }
+        }
+        if opret == tapret {
+            return Err(InvalidSealKeychains::Shared(opret.into_inner()));
+        }
+        Ok(SealKeychains { opret, tapret })
+    }
+
+    pub fn for_method(&self, method: Method) -> Keychain {
+        match method {
+            Method::OpretFirst => self.opret,
+            Method::TapretFirst => self.tapret,
+        }
+    }
+
+    pub fn contains(&self, keychain: impl Into<Keychain>) -> bool {
+        let keychain = keychain.into();
+        keychain == self.opret || keychain == self.tapret
+    }
+}
+
+/// Tapret tweaks known to a descriptor, including the ones made by witness
+/// transactions which are not confirmed yet and thus can be abandoned.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct TapretTweakSet {
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_tweaks"))]
+    tweaks: HashMap<Terminal, Vec<TapretCommitment>>,
+    /// Tweaks made by witness transactions which are not confirmed yet; they
+    /// are also present in `tweaks`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pending_tweaks: HashMap<Txid, (Terminal, TapretCommitment)>,
+}
+
+impl TapretTweakSet {
+    /// Adds tweak to the terminal derivation, unless it is already known.
+    pub fn add(&mut self, terminal: Terminal, tweak: TapretCommitment) {
+        let list = self.tweaks.entry(terminal).or_default();
+        if !list.contains(&tweak) {
+            list.push(tweak);
+        }
+    }
+
+    /// Adds tweak made by a witness transaction which is not confirmed yet.
+    pub fn add_pending(&mut self, witness: Txid, terminal: Terminal, tweak: TapretCommitment) {
+        self.pending_tweaks
+            .insert(witness, (terminal, tweak.clone()));
+        self.add(terminal, tweak);
+    }
+
+    pub fn pending_witnesses(&self) -> Vec<Txid> { self.pending_tweaks.keys().copied().collect() }
+
+    /// Confirms tweak of the witness together with the same tweak made by other
+    /// pending witnesses. Returns `false` if the witness has no pending tweak.
+    pub fn confirm(&mut self, witness: Txid) -> bool {
+        let Some(confirmed) = self.pending_tweaks.remove(&witness) else {
+            return false;
+        };
+        self.pending_tweaks.retain(|_, tweak| *tweak != confirmed);
+        true
+    }
+
+    /// Removes pending tweak of the witness. The tweak itself is kept if other
+    /// pending witnesses have made it as well.
+    pub fn abandon(&mut self, witness: Txid) -> Option<(Terminal, TapretCommitment)> {
+        let abandoned = self.pending_tweaks.remove(&witness)?;
+        if self.pending_tweaks.values().any(|tweak| *tweak == abandoned) {
+            return Some(abandoned);
+        }
+        let (terminal, commitment) = &abandoned;
+        if let Some(list) = self.tweaks.get_mut(terminal) {
+            list.retain(|tweak| tweak != commitment);
+            if list.is_empty() {
+                self.tweaks.remove(terminal);
+            }
+        }
+        Some(abandoned)
+    }
+
+    /// Lists terminals having tweaks, in the ascending order.
+    pub fn terminals(&self) -> Vec<Terminal> {
+        let mut terminals = self.tweaks.keys().copied().collect::<Vec<_>>();
+        terminals.sort();
+        terminals
+    }
+
+    /// Lists tweaks of the terminal in the order they were added.
+    pub fn tweaks(&self, terminal: Terminal) -> &[TapretCommitment] {
+        self.tweaks
+            .get(&terminal)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Returns the most recently added tweak of the terminal.
+    pub fn latest(&self, terminal: Terminal) -> Option<&TapretCommitment> {
+        self.tweaks(terminal).last()
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Debug)]
 #[cfg_attr(
     feature = "serde",
@@ -117,29 +328,89 @@ impl From<RgbKeychain> for Keychain {
 )]
 pub struct TapretKey<K: DeriveXOnly = XpubDerivable> {
     pub internal_key: K,
-    // TODO: Allow multiple tweaks per index by introducing derivation using new Terminal trait
-    pub tweaks: HashMap<Terminal, TapretCommitment>,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub tweaks: TapretTweakSet,
+    /// Method used to close seals defined by the wallet. Wallets using opret
+    /// never tweak their outputs, which is required by some signers which are
+    /// unable to sign tapret-tweaked inputs.
+    #[cfg_attr(feature = "serde", serde(default = "tapret_first"))]
+    pub close_method: CloseMethod,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub keychains: SealKeychains,
 }
 
 impl<K: DeriveXOnly> TapretKey<K> {
     pub fn new_unfunded(internal_key: K) -> Self {
         TapretKey {
             internal_key,
-            tweaks: empty!(),
+            tweaks: default!(),
+            close_method: CloseMethod::TapretFirst,
+            keychains: default!(),
+        }
+    }
+
+    /// Constructs taproot wallet descriptor closing seals with opret
+    /// commitments.
+    pub fn new_opret(internal_key: K) -> Self {
+        TapretKey {
+            internal_key,
+            tweaks: default!(),
+            close_method: CloseMethod::OpretFirst,
+            keychains: default!(),
         }
     }
+
+    /// Uses custom keychains for the seal outputs.
+    pub fn with_seal_keychains(mut self, keychains: SealKeychains) -> Self {
+        self.keychains = keychains;
+        self
+    }
+
+    /// Derives script for the terminal with the given tapret tweak, even if
+    /// the tweak is not known to the descriptor.
+    pub fn derive_tweaked(&self, terminal: Terminal, tweak: &TapretCommitment) -> DerivedScript {
+        let internal_key = self.internal_key.derive(terminal.keychain, terminal.index);
+        let tap_tree = TapTree::with_single_leaf(TapScript::commit(tweak));
+        DerivedScript::TaprootScript(internal_key.into(), tap_tree)
+    }
+}
+
+#[cfg(feature = "serde")]
+fn tapret_first() -> CloseMethod { CloseMethod::TapretFirst }
+
+/// Before multiple tweaks per terminal were supported descriptors were
+/// serialized with a single tweak per terminal, which we still accept.
+#[cfg(feature = "serde")]
+fn deserialize_tweaks<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<Terminal, Vec<TapretCommitment>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(crate = "serde_crate", untagged)]
+    enum Tweaks {
+        Single(TapretCommitment),
+        Multiple(Vec<TapretCommitment>),
+    }
+
+    let tweaks = <HashMap<Terminal, Tweaks> as serde::Deserialize>::deserialize(deserializer)?;
+    Ok(tweaks
+        .into_iter()
+        .map(|(terminal, tweaks)| match tweaks {
+            Tweaks::Single(tweak) => (terminal, vec![tweak]),
+            Tweaks::Multiple(tweaks) => (terminal, tweaks),
+        })
+        .collect())
 }
 
 impl<K: DeriveXOnly> Derive<DerivedScript> for TapretKey<K> {
     #[inline]
-    fn default_keychain(&self) -> Keychain { RgbKeychain::Rgb.into() }
+    fn default_keychain(&self) -> Keychain { self.keychains.opret }
 
     fn keychains(&self) -> BTreeSet<Keychain> {
         bset![
             RgbKeychain::External.into(),
             RgbKeychain::Internal.into(),
-            RgbKeychain::Rgb.into(),
-            RgbKeychain::Tapret.into(),
+            self.keychains.opret,
+            self.keychains.tapret,
         ]
     }
 
@@ -151,34 +422,24 @@ impl<K: DeriveXOnly> Derive<DerivedScript> for TapretKey<K> {
         let keychain = keychain.into();
         let index = index.into();
         let terminal = Terminal::new(keychain, index);
-        let internal_key = self.internal_key.derive(keychain, index);
-        if keychain.into_inner() == RgbKeychain::Tapret as u8 {
-            if let Some(tweak) = self.tweaks.get(&terminal) {
-                let script_commitment = TapScript::commit(tweak);
-                let tap_tree = TapTree::with_single_leaf(script_commitment);
-                return DerivedScript::TaprootScript(internal_key.into(), tap_tree);
+        // Terminals with multiple tweaks are derived using the latest one; the
+        // rest of them are available via `derive_tapret_tweaked`
+        if keychain == self.keychains.tapret {
+            if let Some(tweak) = self.tweaks.latest(terminal) {
+                return self.derive_tweaked(terminal, tweak);
             }
         }
+        let internal_key = self.internal_key.derive(keychain, index);
         DerivedScript::TaprootKeyOnly(internal_key.into())
     }
 }
 
 impl<K: DeriveXOnly> From<K> for TapretKey<K> {
-    fn from(tr: K) -> Self {
-        TapretKey {
-            internal_key: tr,
-            tweaks: none!(),
-        }
-    }
+    fn from(tr: K) -> Self { TapretKey::new_unfunded(tr) }
 }
 
 impl<K: DeriveXOnly> From<TrKey<K>> for TapretKey<K> {
-    fn from(tr: TrKey<K>) -> Self {
-        TapretKey {
-            internal_key: tr.into_internal_key(),
-            tweaks: none!(),
-        }
-    }
+    fn from(tr: TrKey<K>) -> Self { TapretKey::new_unfunded(tr.into_internal_key()) }
 }
 
 impl<K: DeriveXOnly> Descriptor<K> for TapretKey<K> {
@@ -213,19 +474,563 @@ impl<K: DeriveXOnly> Descriptor<K> for TapretKey<K> {
 }
 
 impl<K: DeriveXOnly> DescriptorRgb<K> for TapretKey<K> {
-    fn seal_close_method(&self) -> CloseMethod { CloseMethod::TapretFirst }
+    fn seal_close_method(&self) -> CloseMethod { self.close_method }
+
+    fn seal_keychains(&self) -> SealKeychains { self.keychains }
 
     fn add_tapret_tweak(
         &mut self,
         terminal: Terminal,
         tweak: TapretCommitment,
-    ) -> Result<(), TapTweakAlreadyAssigned> {
-        if self.tweaks.contains_key(&terminal) {
-            return Err(TapTweakAlreadyAssigned(terminal));
+    ) -> Result<(), TapretUnsupported> {
+        self.tweaks.add(terminal, tweak);
+        Ok(())
+    }
+
+    fn add_pending_tapret_tweak(
+        &mut self,
+        witness: Txid,
+        terminal: Terminal,
+        tweak: TapretCommitment,
+    ) -> Result<(), TapretUnsupported> {
+        self.tweaks.add_pending(witness, terminal, tweak);
+        Ok(())
+    }
+
+    fn pending_tapret_witnesses(&self) -> Vec<Txid> { self.tweaks.pending_witnesses() }
+
+    fn confirm_tapret_tweak(&mut self, witness: Txid) -> bool { self.tweaks.confirm(witness) }
+
+    fn abandon_tapret_tweak(&mut self, witness: Txid) -> Option<(Terminal, TapretCommitment)> {
+        self.tweaks.abandon(witness)
+    }
+
+    fn tapret_terminals(&self) -> Vec<Terminal> { self.tweaks.terminals() }
+
+    fn tapret_tweaks(&self, terminal: Terminal) -> &[TapretCommitment] {
+        self.tweaks.tweaks(terminal)
+    }
+
+    fn derive_tapret_tweaked(&self, terminal: Terminal) -> Vec<DerivedScript> {
+        self.tapret_tweaks(terminal)
+            .iter()
+            .map(|tweak| self.derive_tweaked(terminal, tweak))
+            .collect()
+    }
+
+    fn derive_with_tapret(
+        &self,
+        terminal: Terminal,
+        tweak: &TapretCommitment,
+    ) -> Option<DerivedScript> {
+        Some(self.derive_tweaked(terminal, tweak))
+    }
+}
+
+/// Nested segwit v0 single-key descriptor, `sh(wpkh(KEY))`, which can host
+/// only opret seals.
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct ShWpkh<K: DeriveCompr = XpubDerivable> {
+    pub key: K,
+    /// Keychains for the seal outputs; only the opret one is used.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub keychains: SealKeychains,
+}
+
+impl<K: DeriveCompr> ShWpkh<K> {
+    /// Uses custom keychains for the seal outputs.
+    pub fn with_seal_keychains(mut self, keychains: SealKeychains) -> Self {
+        self.keychains = keychains;
+        self
+    }
+}
+
+impl<K: DeriveCompr> From<K> for ShWpkh<K> {
+    fn from(key: K) -> Self {
+        ShWpkh {
+            key,
+            keychains: default!(),
         }
-        self.tweaks.insert(terminal, tweak);
+    }
+}
+
+impl<K: DeriveCompr> Derive<DerivedScript> for ShWpkh<K> {
+    #[inline]
+    fn default_keychain(&self) -> Keychain { RgbKeychain::External.into() }
+
+    fn keychains(&self) -> BTreeSet<Keychain> {
+        bset![RgbKeychain::External.into(), RgbKeychain::Internal.into(), self.keychains.opret]
+    }
+
+    fn derive(
+        &self,
+        keychain: impl Into<Keychain>,
+        index: impl Into<NormalIndex>,
+    ) -> DerivedScript {
+        let pk = self.key.derive(keychain, index);
+        let witness_program = ScriptPubkey::p2wpkh(WPubkeyHash::from(pk));
+        DerivedScript::Bip13(RedeemScript::from_unsafe(witness_program.to_vec()))
+    }
+}
+
+impl<K: DeriveCompr> Descriptor<K> for ShWpkh<K> {
+    fn class(&self) -> SpkClass { SpkClass::P2sh }
+
+    fn keys<'a>(&'a self) -> impl Iterator<Item = &'a K>
+    where K: 'a {
+        iter::once(&self.key)
+    }
+    fn vars<'a>(&'a self) -> impl Iterator<Item = &'a ()>
+    where (): 'a {
+        iter::empty()
+    }
+    fn xpubs(&self) -> impl Iterator<Item = &XpubSpec> { iter::once(self.key.xpub_spec()) }
+
+    fn compr_keyset(&self, terminal: Terminal) -> IndexMap<CompressedPk, KeyOrigin> {
+        let mut map = IndexMap::with_capacity(1);
+        let key = self.key.derive(terminal.keychain, terminal.index);
+        map.insert(key, KeyOrigin::with(self.key.xpub_spec().origin().clone(), terminal));
+        map
+    }
+
+    fn xonly_keyset(&self, _terminal: Terminal) -> IndexMap<XOnlyPk, TapDerivation> {
+        IndexMap::new()
+    }
+}
+
+/// Maximal number of keys in a `multi` script.
+const MAX_MULTISIG_KEYS: usize = 16;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(
+    "multisig descriptor can't require {threshold} of {keys} signatures; the threshold must be \
+     non-zero and up to 16 keys are supported"
+)]
+pub struct InvalidMultisig {
+    pub threshold: u8,
+    pub keys: usize,
+}
+
+/// Segwit v0 multisig descriptor, `wsh(multi(THRESHOLD,KEYS...))`, which can
+/// host only opret seals.
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(
+        crate = "serde_crate",
+        rename_all = "camelCase",
+        try_from = "UncheckedWshMulti<K>",
+        bound(deserialize = "K: serde::Deserialize<'de>")
+    )
+)]
+pub struct WshMulti<K: DeriveCompr = XpubDerivable> {
+    /// Number of signatures required to spend, which is checked on
+    /// construction together with the keys.
+    threshold: u8,
+    keys: Vec<K>,
+    /// Keychains for the seal outputs; only the opret one is used.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub keychains: SealKeychains,
+}
+
+/// Deserialized [`WshMulti`] which threshold is not checked yet.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(crate = "serde_crate", rename_all = "camelCase")]
+struct UncheckedWshMulti<K: DeriveCompr> {
+    threshold: u8,
+    keys: Vec<K>,
+    #[serde(default)]
+    keychains: SealKeychains,
+}
+
+#[cfg(feature = "serde")]
+impl<K: DeriveCompr> TryFrom<UncheckedWshMulti<K>> for WshMulti<K> {
+    type Error = InvalidMultisig;
+
+    fn try_from(descr: UncheckedWshMulti<K>) -> Result<Self, Self::Error> {
+        Ok(WshMulti::new(descr.threshold, descr.keys)?.with_seal_keychains(descr.keychains))
+    }
+}
+
+impl<K: DeriveCompr> WshMulti<K> {
+    pub fn new(threshold: u8, keys: Vec<K>) -> Result<Self, InvalidMultisig> {
+        if threshold == 0 || threshold as usize > keys.len() || keys.len() > MAX_MULTISIG_KEYS {
+            return Err(InvalidMultisig {
+                threshold,
+                keys: keys.len(),
+            });
+        }
+        Ok(WshMulti {
+            threshold,
+            keys,
+            keychains: default!(),
+        })
+    }
+
+    pub fn threshold(&self) -> u8 { self.threshold }
+
+    pub fn participants(&self) -> &[K] { &self.keys }
+
+    /// Uses custom keychains for the seal outputs.
+    pub fn with_seal_keychains(mut self, keychains: SealKeychains) -> Self {
+        self.keychains = keychains;
+        self
+    }
+
+    /// Constructs `multi` witness script for the given derivation terminal.
+    pub fn witness_script(&self, terminal: Terminal) -> WitnessScript {
+        const OP_PUSHNUM_1: u8 = 0x51;
+        const OP_CHECKMULTISIG: u8 = 0xae;
+        const OP_PUSHBYTES_33: u8 = 0x21;
+
+        let mut script = Vec::with_capacity(self.keys.len() * 34 + 3);
+        script.push(OP_PUSHNUM_1 + self.threshold - 1);
+        for key in &self.keys {
+            let pk = key.derive(terminal.keychain, terminal.index);
+            script.push(OP_PUSHBYTES_33);
+            script.extend(pk.to_byte_array());
+        }
+        script.push(OP_PUSHNUM_1 + self.keys.len() as u8 - 1);
+        script.push(OP_CHECKMULTISIG);
+        WitnessScript::from_unsafe(script)
+    }
+}
+
+impl<K: DeriveCompr> Derive<DerivedScript> for WshMulti<K> {
+    #[inline]
+    fn default_keychain(&self) -> Keychain { RgbKeychain::External.into() }
+
+    fn keychains(&self) -> BTreeSet<Keychain> {
+        bset![RgbKeychain::External.into(), RgbKeychain::Internal.into(), self.keychains.opret]
+    }
+
+    fn derive(
+        &self,
+        keychain: impl Into<Keychain>,
+        index: impl Into<NormalIndex>,
+    ) -> DerivedScript {
+        DerivedScript::Segwit(self.witness_script(Terminal::new(keychain.into(), index.into())))
+    }
+}
+
+impl<K: DeriveCompr> Descriptor<K> for WshMulti<K> {
+    fn class(&self) -> SpkClass { SpkClass::P2wsh }
+
+    fn keys<'a>(&'a self) -> impl Iterator<Item = &'a K>
+    where K: 'a {
+        self.keys.iter()
+    }
+    fn vars<'a>(&'a self) -> impl Iterator<Item = &'a ()>
+    where (): 'a {
+        iter::empty()
+    }
+    fn xpubs(&self) -> impl Iterator<Item = &XpubSpec> { self.keys.iter().map(K::xpub_spec) }
+
+    fn compr_keyset(&self, terminal: Terminal) -> IndexMap<CompressedPk, KeyOrigin> {
+        self.keys
+            .iter()
+            .map(|xpub| {
+                let key = xpub.derive(terminal.keychain, terminal.index);
+                (key, KeyOrigin::with(xpub.xpub_spec().origin().clone(), terminal))
+            })
+            .collect()
+    }
+
+    fn xonly_keyset(&self, _terminal: Terminal) -> IndexMap<XOnlyPk, TapDerivation> {
+        IndexMap::new()
+    }
+}
+
+/// Tagged hash as defined in BIP-340.
+fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag = Sha256::digest(tag.as_bytes());
+    let mut engine = Sha256::new();
+    engine.update(tag);
+    engine.update(tag);
+    for chunk in data {
+        engine.update(chunk);
+    }
+    engine.finalize().into()
+}
+
+/// Converts hash into a scalar modulo the curve order. Since the order is
+/// close to 2^256, a single subtraction is always sufficient.
+fn hash_to_scalar(hash: [u8; 32]) -> Scalar {
+    const ORDER: [u8; 32] = [
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xfe, 0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36,
+        0x41, 0x41,
+    ];
+    Scalar::from_be_bytes(hash).unwrap_or_else(|_| {
+        let mut reduced = [0u8; 32];
+        let mut borrow = 0i16;
+        for pos in (0..32).rev() {
+            let diff = hash[pos] as i16 - ORDER[pos] as i16 - borrow;
+            borrow = (diff < 0) as i16;
+            reduced[pos] = diff.rem_euclid(0x100) as u8;
+        }
+        Scalar::from_be_bytes(reduced).expect("reduced modulo curve order")
+    })
+}
+
+/// Aggregates public keys with MuSig2 `KeyAgg` algorithm defined in BIP-327.
+/// The order of the keys matters; descriptors sort them with `KeySort` first.
+///
+/// # Panics
+///
+/// If the aggregated key is a point at infinity, which can't happen unless
+/// the keys were crafted by breaking discrete logarithm.
+pub fn musig_key_agg(keys: &[CompressedPk]) -> XOnlyPk {
+    let serialized = keys
+        .iter()
+        .map(CompressedPk::to_byte_array)
+        .collect::<Vec<_>>();
+    let list = serialized.iter().map(|pk| &pk[..]).collect::<Vec<_>>();
+    let list_hash = tagged_hash("KeyAgg list", &list);
+    let second = serialized.iter().find(|pk| **pk != serialized[0]);
+    let points = keys
+        .iter()
+        .zip(&serialized)
+        .map(|(pk, ser)| {
+            if Some(ser) == second {
+                return **pk;
+            }
+            let coeff = hash_to_scalar(tagged_hash("KeyAgg coefficient", &[&list_hash, ser]));
+            pk.mul_tweak(SECP256K1, &coeff)
+                .expect("zero key aggregation coefficient")
+        })
+        .collect::<Vec<_>>();
+    let aggregated = PublicKey::combine_keys(&points.iter().collect::<Vec<_>>())
+        .expect("MuSig2 aggregated key at infinity");
+    XOnlyPk::from(aggregated)
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display("MuSig2 descriptor requires at least two keys")]
+pub struct InvalidMusig;
+
+/// Taproot multisig descriptor, analogous to `tr(musig(KEY1,KEY2,...))`
+/// defined in BIP-390, where the internal key is an N-of-N MuSig2 aggregate of
+/// the keys derived for each terminal. The keys are sorted with BIP-327
+/// `KeySort` before the aggregation, so their order doesn't matter.
+///
+/// Since the outputs have no script tree, the descriptor can host tapret
+/// commitments the same way [`TapretKey`] does: a tapret leaf becomes the
+/// only leaf of the tree. Spending requires signers supporting MuSig2, which
+/// find the derivation of their keys among the BIP-32 derivations of the
+/// PSBT inputs.
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(
+        crate = "serde_crate",
+        rename_all = "camelCase",
+        try_from = "UncheckedTrMusig<K>",
+        bound(deserialize = "K: serde::Deserialize<'de>")
+    )
+)]
+pub struct TrMusig<K: DeriveCompr = XpubDerivable> {
+    /// Participant keys, which are checked on construction.
+    keys: Vec<K>,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub tweaks: TapretTweakSet,
+    /// Method used to close seals defined by the wallet.
+    pub close_method: CloseMethod,
+    pub keychains: SealKeychains,
+}
+
+/// Deserialized [`TrMusig`] which keys are not checked yet.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(crate = "serde_crate", rename_all = "camelCase")]
+struct UncheckedTrMusig<K: DeriveCompr> {
+    keys: Vec<K>,
+    #[serde(flatten)]
+    tweaks: TapretTweakSet,
+    #[serde(default = "tapret_first")]
+    close_method: CloseMethod,
+    #[serde(default)]
+    keychains: SealKeychains,
+}
+
+#[cfg(feature = "serde")]
+impl<K: DeriveCompr> TryFrom<UncheckedTrMusig<K>> for TrMusig<K> {
+    type Error = InvalidMusig;
+
+    fn try_from(descr: UncheckedTrMusig<K>) -> Result<Self, Self::Error> {
+        let mut musig = TrMusig::new_unfunded(descr.keys)?.with_seal_keychains(descr.keychains);
+        musig.tweaks = descr.tweaks;
+        musig.close_method = descr.close_method;
+        Ok(musig)
+    }
+}
+
+impl<K: DeriveCompr> TrMusig<K> {
+    pub fn new_unfunded(keys: Vec<K>) -> Result<Self, InvalidMusig> {
+        if keys.len() < 2 {
+            return Err(InvalidMusig);
+        }
+        Ok(TrMusig {
+            keys,
+            tweaks: default!(),
+            close_method: CloseMethod::TapretFirst,
+            keychains: default!(),
+        })
+    }
+
+    /// Constructs MuSig2 wallet descriptor closing seals with opret
+    /// commitments.
+    pub fn new_opret(keys: Vec<K>) -> Result<Self, InvalidMusig> {
+        let mut musig = Self::new_unfunded(keys)?;
+        musig.close_method = CloseMethod::OpretFirst;
+        Ok(musig)
+    }
+
+    /// Uses custom keychains for the seal outputs.
+    pub fn with_seal_keychains(mut self, keychains: SealKeychains) -> Self {
+        self.keychains = keychains;
+        self
+    }
+
+    pub fn participants(&self) -> &[K] { &self.keys }
+
+    /// Derives aggregated internal key for the terminal.
+    pub fn internal_key(&self, terminal: Terminal) -> InternalPk {
+        let mut keys = self
+            .keys
+            .iter()
+            .map(|key| key.derive(terminal.keychain, terminal.index))
+            .collect::<Vec<_>>();
+        keys.sort_by_key(CompressedPk::to_byte_array);
+        InternalPk::from_unchecked(musig_key_agg(&keys))
+    }
+
+    /// Derives script for the terminal with the given tapret tweak, even if
+    /// the tweak is not known to the descriptor.
+    pub fn derive_tweaked(&self, terminal: Terminal, tweak: &TapretCommitment) -> DerivedScript {
+        let tap_tree = TapTree::with_single_leaf(TapScript::commit(tweak));
+        DerivedScript::TaprootScript(self.internal_key(terminal), tap_tree)
+    }
+}
+
+impl<K: DeriveCompr> Derive<DerivedScript> for TrMusig<K> {
+    #[inline]
+    fn default_keychain(&self) -> Keychain { self.keychains.opret }
+
+    fn keychains(&self) -> BTreeSet<Keychain> {
+        bset![
+            RgbKeychain::External.into(),
+            RgbKeychain::Internal.into(),
+            self.keychains.opret,
+            self.keychains.tapret,
+        ]
+    }
+
+    fn derive(
+        &self,
+        keychain: impl Into<Keychain>,
+        index: impl Into<NormalIndex>,
+    ) -> DerivedScript {
+        let terminal = Terminal::new(keychain.into(), index.into());
+        if terminal.keychain == self.keychains.tapret {
+            if let Some(tweak) = self.tweaks.latest(terminal) {
+                return self.derive_tweaked(terminal, tweak);
+            }
+        }
+        DerivedScript::TaprootKeyOnly(self.internal_key(terminal))
+    }
+}
+
+impl<K: DeriveCompr> Descriptor<K> for TrMusig<K> {
+    fn class(&self) -> SpkClass { SpkClass::P2tr }
+
+    fn keys<'a>(&'a self) -> impl Iterator<Item = &'a K>
+    where K: 'a {
+        self.keys.iter()
+    }
+    fn vars<'a>(&'a self) -> impl Iterator<Item = &'a ()>
+    where (): 'a {
+        iter::empty()
+    }
+    fn xpubs(&self) -> impl Iterator<Item = &XpubSpec> { self.keys.iter().map(K::xpub_spec) }
+
+    /// Provides derivation of the participant keys, which are not x-only keys
+    /// and can't be put into the taproot derivations.
+    fn compr_keyset(&self, terminal: Terminal) -> IndexMap<CompressedPk, KeyOrigin> {
+        self.keys
+            .iter()
+            .map(|xpub| {
+                let key = xpub.derive(terminal.keychain, terminal.index);
+                (key, KeyOrigin::with(xpub.xpub_spec().origin().clone(), terminal))
+            })
+            .collect()
+    }
+
+    fn xonly_keyset(&self, _terminal: Terminal) -> IndexMap<XOnlyPk, TapDerivation> {
+        IndexMap::new()
+    }
+}
+
+impl<K: DeriveCompr> DescriptorRgb<K> for TrMusig<K> {
+    fn seal_close_method(&self) -> CloseMethod { self.close_method }
+
+    fn seal_keychains(&self) -> SealKeychains { self.keychains }
+
+    fn add_tapret_tweak(
+        &mut self,
+        terminal: Terminal,
+        tweak: TapretCommitment,
+    ) -> Result<(), TapretUnsupported> {
+        self.tweaks.add(terminal, tweak);
+        Ok(())
+    }
+
+    fn add_pending_tapret_tweak(
+        &mut self,
+        witness: Txid,
+        terminal: Terminal,
+        tweak: TapretCommitment,
+    ) -> Result<(), TapretUnsupported> {
+        self.tweaks.add_pending(witness, terminal, tweak);
         Ok(())
     }
+
+    fn pending_tapret_witnesses(&self) -> Vec<Txid> { self.tweaks.pending_witnesses() }
+
+    fn confirm_tapret_tweak(&mut self, witness: Txid) -> bool { self.tweaks.confirm(witness) }
+
+    fn abandon_tapret_tweak(&mut self, witness: Txid) -> Option<(Terminal, TapretCommitment)> {
+        self.tweaks.abandon(witness)
+    }
+
+    fn tapret_terminals(&self) -> Vec<Terminal> { self.tweaks.terminals() }
+
+    fn tapret_tweaks(&self, terminal: Terminal) -> &[TapretCommitment] {
+        self.tweaks.tweaks(terminal)
+    }
+
+    fn derive_tapret_tweaked(&self, terminal: Terminal) -> Vec<DerivedScript> {
+        self.tapret_tweaks(terminal)
+            .iter()
+            .map(|tweak| self.derive_tweaked(terminal, tweak))
+            .collect()
+    }
+
+    fn derive_with_tapret(
+        &self,
+        terminal: Terminal,
+        tweak: &TapretCommitment,
+    ) -> Option<DerivedScript> {
+        Some(self.derive_tweaked(terminal, tweak))
+    }
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, From)]
@@ -246,28 +1051,57 @@ pub enum RgbDescr<S: DeriveSet = XpubDerivable> {
     #[from]
     Wpkh(Wpkh<S::Compr>),
     #[from]
+    ShWpkh(ShWpkh<S::Compr>),
+    #[from]
+    WshMulti(WshMulti<S::Compr>),
+    #[from]
     TapretKey(TapretKey<S::XOnly>),
+    #[from]
+    TrMusig(TrMusig<S::Compr>),
+}
+
+impl<S: DeriveSet> RgbDescr<S> {
+    /// Uses custom keychains for the seal outputs. `wpkh` descriptors always
+    /// use the default keychains and are returned unchanged.
+    pub fn with_seal_keychains(self, keychains: SealKeychains) -> Self {
+        match self {
+            RgbDescr::Wpkh(d) => RgbDescr::Wpkh(d),
+            RgbDescr::ShWpkh(d) => RgbDescr::ShWpkh(d.with_seal_keychains(keychains)),
+            RgbDescr::WshMulti(d) => RgbDescr::WshMulti(d.with_seal_keychains(keychains)),
+            RgbDescr::TapretKey(d) => RgbDescr::TapretKey(d.with_seal_keychains(keychains)),
+            RgbDescr::TrMusig(d) => RgbDescr::TrMusig(d.with_seal_keychains(keychains)),
+        }
+    }
 }
 
 impl<S: DeriveSet> Derive<DerivedScript> for RgbDescr<S> {
     fn default_keychain(&self) -> Keychain {
         match self {
             RgbDescr::Wpkh(d) => d.default_keychain(),
+            RgbDescr::ShWpkh(d) => d.default_keychain(),
+            RgbDescr::WshMulti(d) => d.default_keychain(),
             RgbDescr::TapretKey(d) => d.default_keychain(),
+            RgbDescr::TrMusig(d) => d.default_keychain(),
         }
     }
 
     fn keychains(&self) -> BTreeSet<Keychain> {
         match self {
             RgbDescr::Wpkh(d) => d.keychains(),
+            RgbDescr::ShWpkh(d) => d.keychains(),
+            RgbDescr::WshMulti(d) => d.keychains(),
             RgbDescr::TapretKey(d) => d.keychains(),
+            RgbDescr::TrMusig(d) => d.keychains(),
         }
     }
 
     fn derive(&self, change: impl Into<Keychain>, index: impl Into<NormalIndex>) -> DerivedScript {
         match self {
             RgbDescr::Wpkh(d) => d.derive(change, index),
+            RgbDescr::ShWpkh(d) => d.derive(change, index),
+            RgbDescr::WshMulti(d) => d.derive(change, index),
             RgbDescr::TapretKey(d) => d.derive(change, index),
+            RgbDescr::TrMusig(d) => d.derive(change, index),
         }
     }
 }
@@ -278,7 +1112,10 @@ where Self: Derive<DerivedScript>
     fn class(&self) -> SpkClass {
         match self {
             RgbDescr::Wpkh(d) => d.class(),
+            RgbDescr::ShWpkh(d) => d.class(),
+            RgbDescr::WshMulti(d) => d.class(),
             RgbDescr::TapretKey(d) => d.class(),
+            RgbDescr::TrMusig(d) => d.class(),
         }
     }
 
@@ -286,7 +1123,10 @@ where Self: Derive<DerivedScript>
     where K: 'a {
         match self {
             RgbDescr::Wpkh(d) => d.keys().collect::<Vec<_>>(),
+            RgbDescr::ShWpkh(d) => d.keys().collect::<Vec<_>>(),
+            RgbDescr::WshMulti(d) => d.keys().collect::<Vec<_>>(),
             RgbDescr::TapretKey(d) => d.keys().collect::<Vec<_>>(),
+            RgbDescr::TrMusig(d) => d.keys().collect::<Vec<_>>(),
         }
         .into_iter()
     }
@@ -299,7 +1139,10 @@ where Self: Derive<DerivedScript>
     fn xpubs(&self) -> impl Iterator<Item = &XpubSpec> {
         match self {
             RgbDescr::Wpkh(d) => d.xpubs().collect::<Vec<_>>(),
+            RgbDescr::ShWpkh(d) => d.xpubs().collect::<Vec<_>>(),
+            RgbDescr::WshMulti(d) => d.xpubs().collect::<Vec<_>>(),
             RgbDescr::TapretKey(d) => d.xpubs().collect::<Vec<_>>(),
+            RgbDescr::TrMusig(d) => d.xpubs().collect::<Vec<_>>(),
         }
         .into_iter()
     }
@@ -307,14 +1150,20 @@ where Self: Derive<DerivedScript>
     fn compr_keyset(&self, terminal: Terminal) -> IndexMap<CompressedPk, KeyOrigin> {
         match self {
             RgbDescr::Wpkh(d) => d.compr_keyset(terminal),
+            RgbDescr::ShWpkh(d) => d.compr_keyset(terminal),
+            RgbDescr::WshMulti(d) => d.compr_keyset(terminal),
             RgbDescr::TapretKey(d) => d.compr_keyset(terminal),
+            RgbDescr::TrMusig(d) => d.compr_keyset(terminal),
         }
     }
 
     fn xonly_keyset(&self, terminal: Terminal) -> IndexMap<XOnlyPk, TapDerivation> {
         match self {
             RgbDescr::Wpkh(d) => d.xonly_keyset(terminal),
+            RgbDescr::ShWpkh(d) => d.xonly_keyset(terminal),
+            RgbDescr::WshMulti(d) => d.xonly_keyset(terminal),
             RgbDescr::TapretKey(d) => d.xonly_keyset(terminal),
+            RgbDescr::TrMusig(d) => d.xonly_keyset(terminal),
         }
     }
 }
@@ -325,8 +1174,21 @@ where Self: Derive<DerivedScript>
 {
     fn seal_close_method(&self) -> CloseMethod {
         match self {
-            RgbDescr::Wpkh(_) => CloseMethod::OpretFirst,
+            RgbDescr::Wpkh(_) |
+            RgbDescr::ShWpkh(_) |
+            RgbDescr::WshMulti(_) => CloseMethod::OpretFirst,
             RgbDescr::TapretKey(d) => d.seal_close_method(),
+            RgbDescr::TrMusig(d) => d.seal_close_method(),
+        }
+    }
+
+    fn seal_keychains(&self) -> SealKeychains {
+        match self {
+            RgbDescr::Wpkh(_) => default!(),
+            RgbDescr::ShWpkh(d) => d.keychains,
+            RgbDescr::WshMulti(d) => d.keychains,
+            RgbDescr::TapretKey(d) => d.seal_keychains(),
+            RgbDescr::TrMusig(d) => d.seal_keychains(),
         }
     }
 
@@ -334,20 +1196,211 @@ where Self: Derive<DerivedScript>
         &mut self,
         terminal: Terminal,
         tweak: TapretCommitment,
-    ) -> Result<(), TapTweakAlreadyAssigned> {
+    ) -> Result<(), TapretUnsupported> {
         match self {
-            RgbDescr::Wpkh(_) => panic!("adding tapret tweak to non-taproot descriptor"),
+            RgbDescr::Wpkh(_) |
+            RgbDescr::ShWpkh(_) |
+            RgbDescr::WshMulti(_) => Err(TapretUnsupported(self.class())),
             RgbDescr::TapretKey(d) => d.add_tapret_tweak(terminal, tweak),
+            RgbDescr::TrMusig(d) => d.add_tapret_tweak(terminal, tweak),
+        }
+    }
+
+    fn add_pending_tapret_tweak(
+        &mut self,
+        witness: Txid,
+        terminal: Terminal,
+        tweak: TapretCommitment,
+    ) -> Result<(), TapretUnsupported> {
+        match self {
+            RgbDescr::Wpkh(_) |
+            RgbDescr::ShWpkh(_) |
+            RgbDescr::WshMulti(_) => Err(TapretUnsupported(self.class())),
+            RgbDescr::TapretKey(d) => d.add_pending_tapret_tweak(witness, terminal, tweak),
+            RgbDescr::TrMusig(d) => d.add_pending_tapret_tweak(witness, terminal, tweak),
+        }
+    }
+
+    fn pending_tapret_witnesses(&self) -> Vec<Txid> {
+        match self {
+            RgbDescr::Wpkh(_) |
+            RgbDescr::ShWpkh(_) |
+            RgbDescr::WshMulti(_) => vec![],
+            RgbDescr::TapretKey(d) => d.pending_tapret_witnesses(),
+            RgbDescr::TrMusig(d) => d.pending_tapret_witnesses(),
+        }
+    }
+
+    fn confirm_tapret_tweak(&mut self, witness: Txid) -> bool {
+        match self {
+            RgbDescr::Wpkh(_) |
+            RgbDescr::ShWpkh(_) |
+            RgbDescr::WshMulti(_) => false,
+            RgbDescr::TapretKey(d) => d.confirm_tapret_tweak(witness),
+            RgbDescr::TrMusig(d) => d.confirm_tapret_tweak(witness),
+        }
+    }
+
+    fn abandon_tapret_tweak(&mut self, witness: Txid) -> Option<(Terminal, TapretCommitment)> {
+        match self {
+            RgbDescr::Wpkh(_) |
+            RgbDescr::ShWpkh(_) |
+            RgbDescr::WshMulti(_) => None,
+            RgbDescr::TapretKey(d) => d.abandon_tapret_tweak(witness),
+            RgbDescr::TrMusig(d) => d.abandon_tapret_tweak(witness),
+        }
+    }
+
+    fn tapret_terminals(&self) -> Vec<Terminal> {
+        match self {
+            RgbDescr::Wpkh(_) |
+            RgbDescr::ShWpkh(_) |
+            RgbDescr::WshMulti(_) => vec![],
+            RgbDescr::TapretKey(d) => d.tapret_terminals(),
+            RgbDescr::TrMusig(d) => d.tapret_terminals(),
+        }
+    }
+
+    fn tapret_tweaks(&self, terminal: Terminal) -> &[TapretCommitment] {
+        match self {
+            RgbDescr::Wpkh(_) |
+            RgbDescr::ShWpkh(_) |
+            RgbDescr::WshMulti(_) => &[],
+            RgbDescr::TapretKey(d) => d.tapret_tweaks(terminal),
+            RgbDescr::TrMusig(d) => d.tapret_tweaks(terminal),
+        }
+    }
+
+    fn derive_tapret_tweaked(&self, terminal: Terminal) -> Vec<DerivedScript> {
+        match self {
+            RgbDescr::Wpkh(_) |
+            RgbDescr::ShWpkh(_) |
+            RgbDescr::WshMulti(_) => vec![],
+            RgbDescr::TapretKey(d) => d.derive_tapret_tweaked(terminal),
+            RgbDescr::TrMusig(d) => d.derive_tapret_tweaked(terminal),
+        }
+    }
+
+    fn derive_with_tapret(
+        &self,
+        terminal: Terminal,
+        tweak: &TapretCommitment,
+    ) -> Option<DerivedScript> {
+        match self {
+            RgbDescr::Wpkh(_) |
+            RgbDescr::ShWpkh(_) |
+            RgbDescr::WshMulti(_) => None,
+            RgbDescr::TapretKey(d) => d.derive_with_tapret(terminal, tweak),
+            RgbDescr::TrMusig(d) => d.derive_with_tapret(terminal, tweak),
         }
     }
 }
 
-impl From<StdDescr> for RgbDescr {
-    fn from(descr: StdDescr) -> Self {
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display("descriptors of {0:?} class are not supported by RGB wallets")]
+pub struct UnsupportedDescriptor(pub SpkClass);
+
+impl TryFrom<StdDescr> for RgbDescr {
+    type Error = UnsupportedDescriptor;
+
+    fn try_from(descr: StdDescr) -> Result<Self, Self::Error> {
         match descr {
-            StdDescr::Wpkh(wpkh) => RgbDescr::Wpkh(wpkh),
-            StdDescr::TrKey(tr) => RgbDescr::TapretKey(tr.into()),
-            _ => todo!(),
+            StdDescr::Wpkh(wpkh) => Ok(RgbDescr::Wpkh(wpkh)),
+            StdDescr::TrKey(tr) => Ok(RgbDescr::TapretKey(tr.into())),
+            #[allow(unreachable_patterns)]
+            other => Err(UnsupportedDescriptor(other.class())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn xpub() -> XpubDerivable {
+        XpubDerivable::from_str(
+            "[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtF\
+             ktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1;9;10>/*",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn musig_key_agg_vectors() {
+        // BIP-327 KeyAgg test vectors
+        let keys = [
+            "02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+            "03DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+            "023590A94E768F8E1815C2F24B4D80A8E3149316C3518CE7B7AD338368D038CA66",
+        ]
+        .map(|pk| CompressedPk::from_str(&pk.to_lowercase()).unwrap());
+        let agg = |indexes: &[usize]| {
+            let keys = indexes.iter().map(|no| keys[*no]).collect::<Vec<_>>();
+            musig_key_agg(&keys).to_string().to_uppercase()
+        };
+        let vectors: [(&[usize], &str); 4] = [
+            (&[0, 1, 2], "90539EEDE565F5D054F32CC0C220126889ED1E5D193BAF15AEF344FE59D4610C"),
+            (&[2, 1, 0], "6204DE8B083426DC6EAF9502D27024D53FC826BF7D2012148A0575435DF54B2B"),
+            (&[0, 0, 0], "B436E3BAD62B8CD409969A224731C193D051162D8C5AE8B109306127DA3AA935"),
+            (&[0, 0, 1, 1], "69BC22BFA5D106306E48A20679DE1D7389386124D07571D0D872686028C26A3E"),
+        ];
+        for (indexes, expected) in vectors {
+            assert_eq!(agg(indexes), expected);
+        }
+    }
+
+    #[test]
+    fn musig_tapret() {
+        let key = xpub();
+        assert_eq!(TrMusig::new_unfunded(vec![key.clone()]), Err(InvalidMusig));
+
+        let mut descr = TrMusig::new_unfunded(vec![key.clone(), key]).unwrap();
+        let terminal = Terminal::new(RgbKeychain::Tapret, NormalIndex::from(3u16));
+        let internal_key = descr.internal_key(terminal);
+        let derived = descr.derive(terminal.keychain, terminal.index);
+        assert_eq!(derived, DerivedScript::TaprootKeyOnly(internal_key));
+
+        let mpc = "a3f0b4b1e3c5bb3a4c1d9f1b2bde6bd8da52ab5fc6e1fdb0a8e5f2f3a0c1d2e3";
+        let tweak = TapretCommitment::with(mpc.parse().unwrap(), 0);
+        descr.add_tapret_tweak(terminal, tweak.clone()).unwrap();
+        let tweaked = descr.derive(terminal.keychain, terminal.index);
+        assert_eq!(tweaked.to_internal_pk(), Some(internal_key));
+        assert_eq!(tweaked, descr.derive_with_tapret(terminal, &tweak).unwrap());
+        assert_eq!(descr.compr_keyset(terminal).len(), 1);
+    }
+    #[test]
+    fn wsh_multi_threshold() {
+        let key = xpub();
+        for (threshold, keys) in [(0, 2), (3, 2), (1, 0), (0, 0), (17, 17), (1, 17), (255, 16)] {
+            assert_eq!(
+                WshMulti::new(threshold, vec![key.clone(); keys]),
+                Err(InvalidMultisig { threshold, keys })
+            );
         }
+
+        let terminal = Terminal::new(RgbKeychain::External, NormalIndex::from(0u16));
+        let descr = WshMulti::new(16, vec![key.clone(); 16]).unwrap();
+        let script = descr.witness_script(terminal);
+        let script = script.as_slice();
+        assert_eq!((script[0], script[script.len() - 2]), (0x60, 0x60));
+
+        let descr = WshMulti::new(1, vec![key]).unwrap();
+        let script = descr.witness_script(terminal);
+        let script = script.as_slice();
+        assert_eq!((script[0], script[script.len() - 2]), (0x51, 0x51));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn wsh_multi_deserialize() {
+        let key = xpub();
+        let descr = WshMulti::new(2, vec![key.clone(), key]).unwrap();
+        let mut value = serde_json::to_value(&descr).unwrap();
+        assert_eq!(serde_json::from_value::<WshMulti>(value.clone()).unwrap(), descr);
+
+        value["threshold"] = 3.into();
+        assert!(serde_json::from_value::<WshMulti>(value.clone()).is_err());
+        value["threshold"] = 0.into();
+        assert!(serde_json::from_value::<WshMulti>(value).is_err());
     }
 }
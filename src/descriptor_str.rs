@@ -0,0 +1,706 @@
+// RGB smart contracts for Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Textual representation of RGB wallet descriptors.
+//!
+//! Descriptors follow the syntax of bitcoin output descriptors, extended with
+//! RGB-specific expressions:
+//! - `tapret(KEY)` and `tapret(KEY,{TERMINAL:COMMITMENT,...})` for taproot
+//!   single-key wallets using tapret commitments, where the map lists tapret
+//!   tweaks assigned to the wallet outputs;
+//! - `tr_opret(KEY)` for taproot single-key wallets using opret commitments;
+//! - `tapret(musig(KEY1,KEY2,...))` and `tr_opret(musig(KEY1,KEY2,...))` for
+//!   taproot MuSig2 multisig wallets, which take tapret tweaks in the same way
+//!   as the single-key ones;
+//! - `wpkh(KEY)`, `sh(wpkh(KEY))` and `wsh(multi(THRESHOLD,KEY1,KEY2,...))` for
+//!   wallets using opret commitments.
+//!
+//! Descriptors using non-default seal keychains (see [`SealKeychains`]) are
+//! wrapped into `keychains(OPRET_KEYCHAIN,TAPRET_KEYCHAIN,DESCRIPTOR)`.
+//!
+//! Terminals are written as `KEYCHAIN/INDEX` and tapret commitments as a hex
+//! string of the MPC commitment followed by the nonce byte. The descriptor is
+//! followed by a `#` and the checksum defined in BIP-380.
+//!
+//! Tweaks which are pending (see [`DescriptorRgb::add_pending_tapret_tweak`])
+//! are exported as confirmed ones.
+//!
+//! Tapret tweaks alone can be exported as [`TapretTweaks`], which use a
+//! line-based format with `TERMINAL COMMITMENT` on each line.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use amplify::hex::FromHex;
+use amplify::Wrapper;
+use bp::dbc::tapret::TapretCommitment;
+use bp::seals::txout::CloseMethod;
+use bpstd::{DeriveCompr, DeriveSet, DeriveXOnly, IdxBase, Keychain, NormalIndex, Terminal};
+use commit_verify::mpc;
+use descriptors::{Descriptor, Wpkh};
+
+use crate::{
+    DescriptorRgb, InvalidSealKeychains, RgbDescr, SealKeychains, ShWpkh, TapretKey,
+    TapretTweakSet, TapretUnsupported, TrMusig, WshMulti,
+};
+
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!\
+                             ^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum DescriptorParseError {
+    /// descriptor contains character '{0}' which is not allowed.
+    InvalidChar(char),
+
+    /// descriptor checksum is missing; it must follow the descriptor after a
+    /// '#' character.
+    MissingChecksum,
+
+    /// descriptor checksum '{found}' doesn't match the expected '{expected}'.
+    InvalidChecksum { expected: String, found: String },
+
+    /// invalid descriptor expression '{0}'.
+    InvalidExpr(String),
+
+    /// unknown or unsupported descriptor '{0}'.
+    UnknownDescriptor(String),
+
+    /// invalid number of arguments for '{0}' descriptor.
+    InvalidArgs(String),
+
+    /// invalid key '{0}'. Details: {1}
+    InvalidKey(String, String),
+
+    /// invalid multisig threshold '{0}'.
+    InvalidThreshold(String),
+
+    /// invalid terminal derivation '{0}'.
+    InvalidTerminal(String),
+
+    /// invalid keychain '{0}'.
+    InvalidKeychain(String),
+
+    /// invalid seal keychains: {0}
+    InvalidSealKeychains(InvalidSealKeychains),
+
+    /// invalid tapret commitment '{0}'.
+    InvalidCommitment(String),
+
+    /// invalid tapret tweak entry at line {0}.
+    InvalidTweakLine(usize),
+}
+
+/// Computes descriptor checksum as defined in BIP-380.
+pub fn descriptor_checksum(descr: &str) -> Result<String, DescriptorParseError> {
+    fn polymod(c: u64, val: u64) -> u64 {
+        let c0 = c >> 35;
+        let mut c = ((c & 0x7ffffffff) << 5) ^ val;
+        if c0 & 1 != 0 {
+            c ^= 0xf5dee51989;
+        }
+        if c0 & 2 != 0 {
+            c ^= 0xa9fdca3312;
+        }
+        if c0 & 4 != 0 {
+            c ^= 0x1bab10e32d;
+        }
+        if c0 & 8 != 0 {
+            c ^= 0x3706b1677a;
+        }
+        if c0 & 16 != 0 {
+            c ^= 0x644d626ffd;
+        }
+        c
+    }
+
+    let mut c = 1u64;
+    let mut cls = 0u64;
+    let mut cls_count = 0;
+    for ch in descr.chars() {
+        let pos = INPUT_CHARSET
+            .find(ch)
+            .ok_or(DescriptorParseError::InvalidChar(ch))? as u64;
+        c = polymod(c, pos & 31);
+        cls = cls * 3 + (pos >> 5);
+        cls_count += 1;
+        if cls_count == 3 {
+            c = polymod(c, cls);
+            cls = 0;
+            cls_count = 0;
+        }
+    }
+    if cls_count > 0 {
+        c = polymod(c, cls);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+    Ok((0..8)
+        .map(|j| CHECKSUM_CHARSET[((c >> (5 * (7 - j))) & 31) as usize] as char)
+        .collect())
+}
+
+fn write_checksummed(f: &mut Formatter<'_>, descr: &str) -> fmt::Result {
+    let checksum = descriptor_checksum(descr).map_err(|_| fmt::Error)?;
+    write!(f, "{descr}#{checksum}")
+}
+
+/// Verifies checksum, which is required, returning descriptor without it.
+fn strip_checksum(s: &str) -> Result<&str, DescriptorParseError> {
+    let Some((descr, found)) = s.rsplit_once('#') else {
+        return Err(DescriptorParseError::MissingChecksum);
+    };
+    let expected = descriptor_checksum(descr)?;
+    if expected != found {
+        return Err(DescriptorParseError::InvalidChecksum {
+            expected,
+            found: found.to_owned(),
+        });
+    }
+    Ok(descr)
+}
+
+/// Splits `name(arg1,arg2,...)` expression into the name and top-level
+/// arguments.
+fn parse_expr(s: &str) -> Result<(&str, Vec<&str>), DescriptorParseError> {
+    let err = || DescriptorParseError::InvalidExpr(s.to_owned());
+    let (name, rest) = s.split_once('(').ok_or_else(err)?;
+    let inner = rest.strip_suffix(')').ok_or_else(err)?;
+    let args = split_args(inner).ok_or_else(err)?;
+    Ok((name, args))
+}
+
+/// Splits comma-separated list by the commas which are not nested into
+/// brackets. Returns `None` if the brackets are unbalanced.
+fn split_args(s: &str) -> Option<Vec<&str>> {
+    let mut args = vec![];
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (pos, ch) in s.char_indices() {
+        match ch {
+            '(' | '{' | '[' => depth += 1,
+            ')' | '}' | ']' => depth = depth.checked_sub(1)?,
+            ',' if depth == 0 => {
+                args.push(&s[start..pos]);
+                start = pos + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return None;
+    }
+    args.push(&s[start..]);
+    Some(args)
+}
+
+fn parse_key<K: FromStr>(s: &str) -> Result<K, DescriptorParseError>
+where K::Err: Display {
+    K::from_str(s).map_err(|e| DescriptorParseError::InvalidKey(s.to_owned(), e.to_string()))
+}
+
+fn parse_threshold<T: FromStr>(s: &str) -> Result<T, DescriptorParseError> {
+    T::from_str(s).map_err(|_| DescriptorParseError::InvalidThreshold(s.to_owned()))
+}
+
+/// Wraps descriptor into `keychains(OPRET,TAPRET,DESCR)` expression if it uses
+/// non-default seal keychains.
+fn wrap_keychains(keychains: SealKeychains, descr: String) -> String {
+    if keychains == SealKeychains::default() {
+        return descr;
+    }
+    format!("keychains({},{},{descr})", keychains.opret.into_inner(), keychains.tapret.into_inner())
+}
+
+/// Unwraps `keychains(OPRET,TAPRET,DESCR)` expression, if present, returning
+/// seal keychains together with the name and arguments of the wrapped
+/// descriptor.
+fn parse_keychains(s: &str) -> Result<(SealKeychains, &str, Vec<&str>), DescriptorParseError> {
+    let (name, args) = parse_expr(s)?;
+    if name != "keychains" {
+        return Ok((default!(), name, args));
+    }
+    let [opret, tapret, inner] = args.as_slice() else {
+        return Err(DescriptorParseError::InvalidArgs(s!("keychains")));
+    };
+    let keychains = SealKeychains::new(parse_keychain(opret)?, parse_keychain(tapret)?)
+        .map_err(DescriptorParseError::InvalidSealKeychains)?;
+    let (name, args) = parse_expr(inner)?;
+    Ok((keychains, name, args))
+}
+
+fn parse_keychain(s: &str) -> Result<Keychain, DescriptorParseError> {
+    u8::from_str(s)
+        .map(Keychain::from)
+        .map_err(|_| DescriptorParseError::InvalidKeychain(s.to_owned()))
+}
+
+fn fmt_terminal(terminal: Terminal) -> String {
+    format!("{}/{}", terminal.keychain.into_inner(), terminal.index.index())
+}
+
+fn parse_terminal(s: &str) -> Result<Terminal, DescriptorParseError> {
+    let err = || DescriptorParseError::InvalidTerminal(s.to_owned());
+    let (keychain, index) = s.split_once('/').ok_or_else(err)?;
+    let keychain = u8::from_str(keychain).map_err(|_| err())?;
+    let index = NormalIndex::from_str(index).map_err(|_| err())?;
+    Ok(Terminal::new(Keychain::from(keychain), index))
+}
+
+fn fmt_commitment(commitment: &TapretCommitment) -> String {
+    format!("{}{:02x}", commitment.mpc, commitment.nonce)
+}
+
+fn parse_commitment(s: &str) -> Result<TapretCommitment, DescriptorParseError> {
+    let err = || DescriptorParseError::InvalidCommitment(s.to_owned());
+    if s.len() != 66 || !s.is_ascii() {
+        return Err(err());
+    }
+    let (mpc, nonce) = s.split_at(64);
+    Ok(TapretCommitment {
+        mpc: mpc::Commitment::from_hex(mpc).map_err(|_| err())?,
+        nonce: u8::from_str_radix(nonce, 16).map_err(|_| err())?,
+    })
+}
+
+/// Formats tapret tweaks as `,{TERMINAL:COMMITMENT,...}`, or as an empty
+/// string if there are no tweaks.
+fn fmt_tweaks<K>(descr: &impl DescriptorRgb<K>) -> String {
+    let tweaks = descr
+        .tapret_terminals()
+        .into_iter()
+        .flat_map(|terminal| {
+            descr
+                .tapret_tweaks(terminal)
+                .iter()
+                .map(move |tweak| format!("{}:{}", fmt_terminal(terminal), fmt_commitment(tweak)))
+        })
+        .collect::<Vec<_>>();
+    if tweaks.is_empty() {
+        return empty!();
+    }
+    format!(",{{{}}}", tweaks.join(","))
+}
+
+fn parse_tweaks(s: &str) -> Result<TapretTweakSet, DescriptorParseError> {
+    let inner = s
+        .strip_prefix('{')
+        .and_then(|s| s.strip_suffix('}'))
+        .ok_or_else(|| DescriptorParseError::InvalidExpr(s.to_owned()))?;
+    let mut tweaks = TapretTweakSet::default();
+    for item in inner.split(',').filter(|item| !item.is_empty()) {
+        let (terminal, commitment) = item
+            .split_once(':')
+            .ok_or_else(|| DescriptorParseError::InvalidExpr(item.to_owned()))?;
+        tweaks.add(parse_terminal(terminal)?, parse_commitment(commitment)?);
+    }
+    Ok(tweaks)
+}
+
+/// List of tapret tweaks exported from a wallet descriptor, which can be
+/// imported into a descriptor with the same keys to restore the ability to
+/// spend tweaked outputs.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct TapretTweaks(pub Vec<(Terminal, TapretCommitment)>);
+
+impl TapretTweaks {
+    pub fn export<K>(descr: &impl DescriptorRgb<K>) -> Self {
+        TapretTweaks(
+            descr
+                .tapret_terminals()
+                .into_iter()
+                .flat_map(|terminal| {
+                    descr
+                        .tapret_tweaks(terminal)
+                        .iter()
+                        .map(move |tweak| (terminal, tweak.clone()))
+                })
+                .collect(),
+        )
+    }
+
+    /// Adds tweaks to the descriptor, returning the number of the tweaks which
+    /// were not known to it before.
+    pub fn import_into<K>(
+        &self,
+        descr: &mut impl DescriptorRgb<K>,
+    ) -> Result<usize, TapretUnsupported> {
+        let mut count = 0;
+        for (terminal, tweak) in &self.0 {
+            if !descr.tapret_tweaks(*terminal).contains(tweak) {
+                descr.add_tapret_tweak(*terminal, tweak.clone())?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+impl Display for TapretTweaks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (terminal, tweak) in &self.0 {
+            writeln!(f, "{} {}", fmt_terminal(*terminal), fmt_commitment(tweak))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TapretTweaks {
+    type Err = DescriptorParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tweaks = vec![];
+        for (no, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (terminal, tweak) = line
+                .split_once(' ')
+                .ok_or(DescriptorParseError::InvalidTweakLine(no + 1))?;
+            tweaks.push((parse_terminal(terminal)?, parse_commitment(tweak.trim())?));
+        }
+        Ok(TapretTweaks(tweaks))
+    }
+}
+
+impl<K: DeriveXOnly + Display> TapretKey<K> {
+    fn to_descr_string(&self) -> String {
+        let descr = match self.close_method {
+            CloseMethod::TapretFirst => {
+                format!("tapret({}{})", self.internal_key, fmt_tweaks(self))
+            }
+            CloseMethod::OpretFirst => {
+                format!("tr_opret({}{})", self.internal_key, fmt_tweaks(self))
+            }
+        };
+        wrap_keychains(self.keychains, descr)
+    }
+}
+
+impl<K: DeriveXOnly + FromStr> TapretKey<K>
+where K::Err: Display
+{
+    fn from_expr(name: &str, args: &[&str]) -> Result<Self, DescriptorParseError> {
+        let close_method = match name {
+            "tapret" => CloseMethod::TapretFirst,
+            "tr_opret" => CloseMethod::OpretFirst,
+            _ => return Err(DescriptorParseError::UnknownDescriptor(name.to_owned())),
+        };
+        let (key, tweaks) = match args {
+            [key] => (key, default!()),
+            [key, tweaks] => (key, parse_tweaks(tweaks)?),
+            _ => return Err(DescriptorParseError::InvalidArgs(name.to_owned())),
+        };
+        Ok(TapretKey {
+            internal_key: parse_key(key)?,
+            tweaks,
+            close_method,
+            keychains: default!(),
+        })
+    }
+}
+
+impl<K: DeriveXOnly + Display> Display for TapretKey<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_checksummed(f, &self.to_descr_string())
+    }
+}
+
+impl<K: DeriveXOnly + FromStr> FromStr for TapretKey<K>
+where K::Err: Display
+{
+    type Err = DescriptorParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (keychains, name, args) = parse_keychains(strip_checksum(s)?)?;
+        TapretKey::from_expr(name, &args).map(|d| d.with_seal_keychains(keychains))
+    }
+}
+
+impl<K: DeriveCompr + Display> TrMusig<K> {
+    fn to_descr_string(&self) -> String {
+        let keys = self
+            .participants()
+            .iter()
+            .map(K::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        let name = match self.close_method {
+            CloseMethod::TapretFirst => "tapret",
+            CloseMethod::OpretFirst => "tr_opret",
+        };
+        wrap_keychains(self.keychains, format!("{name}(musig({keys}){})", fmt_tweaks(self)))
+    }
+}
+
+impl<K: DeriveCompr + FromStr> TrMusig<K>
+where K::Err: Display
+{
+    fn from_expr(name: &str, args: &[&str]) -> Result<Self, DescriptorParseError> {
+        let (keys, tweaks) = match args {
+            [keys] => (keys, default!()),
+            [keys, tweaks] => (keys, parse_tweaks(tweaks)?),
+            _ => return Err(DescriptorParseError::InvalidArgs(name.to_owned())),
+        };
+        let ("musig", keys) = parse_expr(keys)? else {
+            return Err(DescriptorParseError::InvalidExpr(keys.to_string()));
+        };
+        let keys = keys
+            .iter()
+            .map(|key| parse_key(key))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut musig = match name {
+            "tapret" => TrMusig::new_unfunded(keys),
+            "tr_opret" => TrMusig::new_opret(keys),
+            _ => return Err(DescriptorParseError::UnknownDescriptor(name.to_owned())),
+        }
+        .map_err(|_| DescriptorParseError::InvalidArgs(s!("musig")))?;
+        musig.tweaks = tweaks;
+        Ok(musig)
+    }
+}
+
+impl<K: DeriveCompr + Display> WshMulti<K> {
+    fn to_descr_string(&self) -> String {
+        let keys = self
+            .participants()
+            .iter()
+            .map(K::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        wrap_keychains(self.keychains, format!("wsh(multi({},{keys}))", self.threshold()))
+    }
+}
+
+impl<S: DeriveSet> Display for RgbDescr<S>
+where
+    S::Compr: Display,
+    S::XOnly: Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let descr = match self {
+            RgbDescr::Wpkh(d) => {
+                format!("wpkh({})", Descriptor::keys(d).next().expect("single key"))
+            }
+            RgbDescr::ShWpkh(d) => wrap_keychains(d.keychains, format!("sh(wpkh({}))", d.key)),
+            RgbDescr::WshMulti(d) => d.to_descr_string(),
+            RgbDescr::TapretKey(d) => d.to_descr_string(),
+            RgbDescr::TrMusig(d) => d.to_descr_string(),
+        };
+        write_checksummed(f, &descr)
+    }
+}
+
+impl<S: DeriveSet> FromStr for RgbDescr<S>
+where
+    S::Compr: FromStr,
+    S::XOnly: FromStr,
+    <S::Compr as FromStr>::Err: Display,
+    <S::XOnly as FromStr>::Err: Display,
+{
+    type Err = DescriptorParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (keychains, name, args) = parse_keychains(strip_checksum(s)?)?;
+        let invalid_args = || DescriptorParseError::InvalidArgs(name.to_owned());
+        let descr = match (name, args.as_slice()) {
+            ("wpkh", [key]) => RgbDescr::Wpkh(Wpkh::from(parse_key::<S::Compr>(key)?)),
+            ("sh", [inner]) => match parse_expr(inner)? {
+                ("wpkh", args) => match args.as_slice() {
+                    [key] => RgbDescr::ShWpkh(ShWpkh::from(parse_key::<S::Compr>(key)?)),
+                    _ => return Err(DescriptorParseError::InvalidArgs(s!("wpkh"))),
+                },
+                (name, _) => {
+                    return Err(DescriptorParseError::UnknownDescriptor(format!("sh({name})")))
+                }
+            },
+            ("wsh", [inner]) => match parse_expr(inner)? {
+                ("multi", args) => {
+                    let [threshold, keys @ ..] = args.as_slice() else {
+                        return Err(DescriptorParseError::InvalidArgs(s!("multi")));
+                    };
+                    let threshold = parse_threshold::<u8>(threshold)?;
+                    let keys = keys
+                        .iter()
+                        .map(|key| parse_key(key))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    RgbDescr::WshMulti(WshMulti::new(threshold, keys).map_err(|_| {
+                        DescriptorParseError::InvalidThreshold(threshold.to_string())
+                    })?)
+                }
+                (name, _) => {
+                    return Err(DescriptorParseError::UnknownDescriptor(format!("wsh({name})")))
+                }
+            },
+            ("tapret" | "tr_opret", [key, ..]) if key.starts_with("musig(") => {
+                RgbDescr::TrMusig(TrMusig::from_expr(name, &args)?)
+            }
+            ("tapret" | "tr_opret", args) => RgbDescr::TapretKey(TapretKey::from_expr(name, args)?),
+            ("wpkh" | "sh" | "wsh", _) => return Err(invalid_args()),
+            (name, _) => return Err(DescriptorParseError::UnknownDescriptor(name.to_owned())),
+        };
+        // `wpkh` descriptors can't use custom keychains
+        if matches!(descr, RgbDescr::Wpkh(_)) && keychains != SealKeychains::default() {
+            return Err(DescriptorParseError::InvalidArgs(s!("keychains")));
+        }
+        Ok(descr.with_seal_keychains(keychains))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bpstd::XpubDerivable;
+
+    use super::*;
+
+    const XPUB: &str = "[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1;9;10>/*";
+
+    fn round_trip(descr: &str) -> RgbDescr {
+        let checksummed = format!("{descr}#{}", descriptor_checksum(descr).unwrap());
+        let parsed = RgbDescr::<XpubDerivable>::from_str(&checksummed).unwrap();
+        assert_eq!(parsed.to_string(), checksummed);
+        parsed
+    }
+
+    #[test]
+    fn checksum_vectors() {
+        // BIP-380 test vectors
+        assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert_eq!(strip_checksum("raw(deadbeef)#89f8spxm"), Ok("raw(deadbeef)"));
+        assert_eq!(strip_checksum("raw(deadbeef)"), Err(DescriptorParseError::MissingChecksum));
+        assert!(matches!(
+            strip_checksum("raw(deadbeef)#"),
+            Err(DescriptorParseError::InvalidChecksum { .. })
+        ));
+        assert!(matches!(
+            strip_checksum("raw(deadbeef)#89f8spxmx"),
+            Err(DescriptorParseError::InvalidChecksum { .. })
+        ));
+        assert!(matches!(
+            strip_checksum("raw(deadbeef)#89f8spxn"),
+            Err(DescriptorParseError::InvalidChecksum { .. })
+        ));
+        assert_eq!(
+            strip_checksum("raw(deadbeef)#8\u{f0}f8spxm"),
+            Err(DescriptorParseError::InvalidChecksum {
+                expected: s!("89f8spxm"),
+                found: s!("8\u{f0}f8spxm")
+            })
+        );
+        assert_eq!(
+            descriptor_checksum("raw(dead\u{f0}beef)"),
+            Err(DescriptorParseError::InvalidChar('\u{f0}'))
+        );
+    }
+
+    #[test]
+    fn round_trip_opret() {
+        round_trip(&format!("wpkh({XPUB})"));
+        round_trip(&format!("sh(wpkh({XPUB}))"));
+        round_trip(&format!("wsh(multi(2,{XPUB},{XPUB}))"));
+        round_trip(&format!("tr_opret({XPUB})"));
+        round_trip(&format!("tr_opret(musig({XPUB},{XPUB}))"));
+        round_trip(&format!("keychains(20,21,sh(wpkh({XPUB})))"));
+    }
+
+    #[test]
+    fn round_trip_tapret() {
+        let descr = round_trip(&format!("tapret({XPUB})"));
+        assert_eq!(descr.tapret_terminals(), vec![]);
+
+        let mpc = "a3f0b4b1e3c5bb3a4c1d9f1b2bde6bd8da52ab5fc6e1fdb0a8e5f2f3a0c1d2e3";
+        let tweaks = format!("10/5:{mpc}00,10/5:{mpc}01,10/7:{mpc}02");
+        let descr = round_trip(&format!("tapret({XPUB},{{{tweaks}}})"));
+        let terminal = Terminal::new(Keychain::from(10), NormalIndex::from(5u16));
+        assert_eq!(descr.tapret_terminals(), vec![
+            terminal,
+            Terminal::new(Keychain::from(10), NormalIndex::from(7u16))
+        ]);
+        let nonces = descr
+            .tapret_tweaks(terminal)
+            .iter()
+            .map(|tweak| tweak.nonce)
+            .collect::<Vec<_>>();
+        assert_eq!(nonces, vec![0, 1]);
+
+        let descr = round_trip(&format!("tapret(musig({XPUB},{XPUB}),{{{tweaks}}})"));
+        assert!(matches!(descr, RgbDescr::TrMusig(_)));
+        assert_eq!(descr.tapret_tweaks(terminal).len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn tweaks_serde() {
+        use bpstd::Txid;
+
+        let mpc = "a3f0b4b1e3c5bb3a4c1d9f1b2bde6bd8da52ab5fc6e1fdb0a8e5f2f3a0c1d2e3";
+        let s = format!("tapret({XPUB},{{10/5:{mpc}00}})");
+        let checksummed = format!("{s}#{}", descriptor_checksum(&s).unwrap());
+        let mut descr = TapretKey::<XpubDerivable>::from_str(&checksummed).unwrap();
+        let terminal = Terminal::new(Keychain::from(10), NormalIndex::from(7u16));
+        let tweak = parse_commitment(&format!("{mpc}01")).unwrap();
+        descr.add_pending_tapret_tweak(Txid::coinbase(), terminal, tweak).unwrap();
+
+        let yaml = serde_yaml::to_string(&descr).unwrap();
+        assert!(yaml.contains("\ntweaks:") && yaml.contains("\npendingTweaks:"));
+        assert_eq!(serde_yaml::from_str::<TapretKey>(&yaml).unwrap(), descr);
+    }
+
+    #[test]
+    fn invalid_descriptors() {
+        let parse = |descr: &str| {
+            let checksummed = format!("{descr}#{}", descriptor_checksum(descr).unwrap());
+            RgbDescr::<XpubDerivable>::from_str(&checksummed)
+        };
+        assert!(matches!(
+            parse(&format!("wsh(multi(3,{XPUB},{XPUB}))")),
+            Err(DescriptorParseError::InvalidThreshold(_))
+        ));
+        assert!(matches!(
+            parse(&format!("tapret(musig({XPUB}))")),
+            Err(DescriptorParseError::InvalidArgs(_))
+        ));
+        assert!(matches!(
+            parse(&format!("tapret({XPUB},{{10/5:00}})")),
+            Err(DescriptorParseError::InvalidCommitment(_))
+        ));
+        assert!(matches!(
+            parse(&format!("pkh({XPUB})")),
+            Err(DescriptorParseError::UnknownDescriptor(_))
+        ));
+        assert_eq!(
+            parse(&format!("keychains(20,20,wpkh({XPUB}))")),
+            Err(DescriptorParseError::InvalidSealKeychains(InvalidSealKeychains::Shared(20)))
+        );
+        assert_eq!(
+            parse(&format!("keychains(1,21,wpkh({XPUB}))")),
+            Err(DescriptorParseError::InvalidSealKeychains(InvalidSealKeychains::Reserved(1)))
+        );
+        assert_eq!(
+            parse(&format!("keychains(20,0,wpkh({XPUB}))")),
+            Err(DescriptorParseError::InvalidSealKeychains(InvalidSealKeychains::Reserved(0)))
+        );
+    }
+}
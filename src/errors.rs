@@ -34,7 +34,8 @@ use rgbstd::persistence::{
 };
 use strict_types::encoding::{DeserializeError, Ident, SerializeError};
 
-use crate::{validation, TapTweakAlreadyAssigned};
+use crate::resolvers::ResolverError;
+use crate::{validation, Layer1, TapretUnsupported};
 
 #[derive(Debug, Display, Error, From)]
 #[display(inner)]
@@ -89,7 +90,8 @@ pub enum WalletError {
 
     /// resolver error: {0}
     #[display(doc_comments)]
-    Resolver(String),
+    #[from]
+    Resolver(ResolverError),
 
     #[from(StockError)]
     #[from(StockErrorAll)]
@@ -162,6 +164,10 @@ pub enum CompositionError {
     /// non-fungible state is not yet supported by the invoices.
     Unsupported,
 
+    /// {0} is not supported as a layer 1 for payments; only bitcoin witness
+    /// transactions can be constructed.
+    UnsupportedLayer1(Layer1),
+
     #[from]
     #[display(inner)]
     Construction(ConstructionError),
@@ -194,9 +200,14 @@ pub enum CompletionError {
     /// the provided PSBT has conflicting descriptor in the taptweak output.
     InconclusiveDerivation,
 
+    /// the provided PSBT is not finalized; it must be signed and finalized
+    /// before the witness transaction can be added to the stash.
+    NotFinalized,
+
+    /// the PSBT commits to RGB data with tapret, which is not supported by
+    /// the wallet descriptor. Details: {0}
     #[from]
-    #[display(inner)]
-    MultipleTweaks(TapTweakAlreadyAssigned),
+    TapretUnsupported(TapretUnsupported),
 
     #[from]
     #[display(inner)]
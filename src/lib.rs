@@ -26,20 +26,34 @@ extern crate amplify;
 extern crate serde_crate as serde;
 
 mod descriptor;
+mod descriptor_str;
 #[allow(hidden_glob_reexports)]
 mod resolvers;
 mod wallet;
 pub mod pay;
 mod errors;
+mod sync;
 #[cfg(feature = "fs")]
 mod store;
 
-pub use descriptor::{DescriptorRgb, RgbDescr, RgbKeychain, TapTweakAlreadyAssigned, TapretKey};
+pub use descriptor::{
+    musig_key_agg, DescriptorRgb, InvalidMultisig, InvalidMusig, InvalidSealKeychains,
+    KeychainParseError, RgbDescr, RgbKeychain, SealKeychains, ShWpkh, TapretKey, TapretTweakSet,
+    TapretUnsupported, TrMusig, UnsupportedDescriptor, WshMulti,
+};
+pub use descriptor_str::{descriptor_checksum, DescriptorParseError, TapretTweaks};
 pub use errors::{CompletionError, CompositionError, HistoryError, PayError, WalletError};
 pub use pay::{TransferParams, WalletProvider};
-#[cfg(any(feature = "electrum_blocking", feature = "esplora_blocking"))]
+#[cfg(any(
+    feature = "electrum_blocking",
+    feature = "esplora_blocking",
+    feature = "bitcoind_rpc",
+    feature = "electrum_async",
+    feature = "esplora_async"
+))]
 pub use resolvers::*;
 pub use rgbstd::*;
 #[cfg(feature = "fs")]
 pub use store::{StoredStock, StoredWallet};
+pub use sync::{recorded_witness_ords, SyncReport, WitnessStatus, WitnessStatusError};
 pub use wallet::{WalletStock, WalletWrapper};
@@ -24,28 +24,32 @@ use std::marker::PhantomData;
 use std::ops::DerefMut;
 
 use bp::dbc::tapret::TapretProof;
-use bp::seals::txout::ExplicitSeal;
+use bp::seals::txout::{CloseMethod, ExplicitSeal};
 use bp::{Outpoint, Sats, ScriptPubkey, Vout};
 use bpstd::{psbt, Address};
 use bpwallet::Wallet;
+use descriptors::{Descriptor, SpkClass};
 use psrgbt::{
     Beneficiary as BpBeneficiary, Psbt, PsbtConstructor, PsbtMeta, RgbPsbt, TapretKeyError,
     TxParams,
 };
-use rgbstd::containers::Transfer;
+use rgbstd::containers::{PubWitness, SealWitness, Transfer};
 use rgbstd::interface::{OutpointFilter, WitnessFilter};
 use rgbstd::invoice::{Amount, Beneficiary, InvoiceState, RgbInvoice};
 use rgbstd::persistence::{IndexProvider, StashProvider, StateProvider, Stock};
-use rgbstd::{ContractId, DataState, XChain, XOutpoint};
+use rgbstd::{ContractId, DataState, Layer1, XChain, XOutpoint, XWitnessId};
 
 use crate::invoice::NonFungible;
 use crate::wallet::WalletWrapper;
-use crate::{CompletionError, CompositionError, DescriptorRgb, PayError, RgbKeychain, Txid};
+use crate::{CompletionError, CompositionError, DescriptorRgb, PayError, Txid};
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct TransferParams {
     pub tx: TxParams,
     pub min_amount: Sats,
+    /// Method for closing seals defined by the transfer. If not given, the
+    /// method preferred by the wallet descriptor is used.
+    pub close_method: Option<CloseMethod>,
 }
 
 impl TransferParams {
@@ -53,8 +57,14 @@ impl TransferParams {
         TransferParams {
             tx: TxParams::with(fee),
             min_amount,
+            close_method: None,
         }
     }
+
+    pub fn with_close_method(mut self, method: CloseMethod) -> Self {
+        self.close_method = Some(method);
+        self
+    }
 }
 
 struct ContractOutpointsFilter<
@@ -124,7 +134,15 @@ where Self::Descr: DescriptorRgb<K>
         mut params: TransferParams,
     ) -> Result<(Psbt, PsbtMeta), CompositionError> {
         let contract_id = invoice.contract.ok_or(CompositionError::NoContract)?;
-        let method = self.descriptor().seal_close_method();
+        let method = params
+            .close_method
+            .unwrap_or_else(|| self.descriptor().seal_close_method());
+        // Witness transactions are constructed as bitcoin PSBTs, which can't
+        // represent confidential Liquid transactions
+        let layer1 = invoice.beneficiary.chain_network().layer1();
+        if layer1 != Layer1::Bitcoin {
+            return Err(CompositionError::UnsupportedLayer1(layer1));
+        }
 
         let iface_name = invoice.iface.clone().ok_or(CompositionError::NoIface)?;
         let iface = stock.iface(iface_name.clone()).map_err(|e| e.to_string())?;
@@ -212,10 +230,21 @@ where Self::Descr: DescriptorRgb<K>
         };
         let prev_outpoints = prev_outputs
             .iter()
-            // TODO: Support liquid
-            .map(|o| o.as_reduced_unsafe())
-            .map(|o| Outpoint::new(o.txid, o.vout));
-        params.tx.change_keychain = RgbKeychain::for_method(method).into();
+            .map(|o| match o {
+                XChain::Bitcoin(seal) => Ok(Outpoint::new(seal.txid, seal.vout)),
+                other => Err(CompositionError::UnsupportedLayer1(other.layer1())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // Seals defined with tapret can be closed only with a tapret commitment,
+        // which requires our wallet to have a taproot output
+        let tapret_host = method == CloseMethod::TapretFirst ||
+            prev_outputs.iter().any(
+                |o| matches!(o, XChain::Bitcoin(seal) if seal.method == CloseMethod::TapretFirst),
+            );
+        if tapret_host && self.descriptor().class() != SpkClass::P2tr {
+            return Err(CompositionError::TapretRequired);
+        }
+        params.tx.change_keychain = self.descriptor().seal_keychains().for_method(method);
         let (mut psbt, mut meta) =
             self.construct_psbt(prev_outpoints, &beneficiaries, params.tx)?;
 
@@ -225,9 +254,11 @@ where Self::Descr: DescriptorRgb<K>
             } else {
                 None
             };
-        psbt.outputs_mut()
-            .find(|o| o.script.is_p2tr() && Some(&o.script) != beneficiary_script.as_ref())
-            .map(|o| o.set_tapret_host().expect("just created"));
+        if tapret_host {
+            psbt.outputs_mut()
+                .find(|o| o.script.is_p2tr() && Some(&o.script) != beneficiary_script.as_ref())
+                .map(|o| o.set_tapret_host().expect("just created"));
+        }
         // TODO: Add descriptor id to the tapret host data
 
         let change_script = meta
@@ -263,6 +294,9 @@ where Self::Descr: DescriptorRgb<K>
             .map_err(|e| e.to_string())?;
 
         let methods = batch.close_method_set();
+        if methods.has_tapret_first() && !psbt.outputs().any(psbt::Output::is_tapret_host) {
+            return Err(CompositionError::TapretRequired);
+        }
         if methods.has_opret_first() {
             let output = psbt.construct_output_expect(ScriptPubkey::op_return(&[]), Sats::ZERO);
             output.set_opret_host().expect("just created");
@@ -283,6 +317,7 @@ where Self::Descr: DescriptorRgb<K>
         let contract_id = invoice.contract.ok_or(CompletionError::NoContract)?;
 
         let fascia = psbt.rgb_commit()?;
+        let witness_txid = psbt.txid();
         if fascia.anchor.has_tapret() {
             let output = psbt
                 .dbc_output::<TapretProof>()
@@ -291,11 +326,15 @@ where Self::Descr: DescriptorRgb<K>
                 .terminal_derivation()
                 .ok_or(CompletionError::InconclusiveDerivation)?;
             let tapret_commitment = output.tapret_commitment()?;
-            self.descriptor_mut()
-                .add_tapret_tweak(terminal, tapret_commitment)?;
+            // The witness is not signed yet and may never get broadcast, so the
+            // tweak remains pending until the witness is mined
+            self.descriptor_mut().add_pending_tapret_tweak(
+                witness_txid,
+                terminal,
+                tapret_commitment,
+            )?;
         }
 
-        let witness_txid = psbt.txid();
         let (beneficiary1, beneficiary2) = match invoice.beneficiary.into_inner() {
             Beneficiary::WitnessVout(pay2vout) => {
                 let s = pay2vout.address.script_pubkey();
@@ -329,3 +368,83 @@ impl<K, D: DescriptorRgb<K>> WalletProvider<K> for Wallet<K, D> {
     fn outpoints(&self) -> impl Iterator<Item = Outpoint> { self.coins().map(|coin| coin.outpoint) }
     fn txids(&self) -> impl Iterator<Item = Txid> { self.transactions().keys().copied() }
 }
+
+/// Replaces the unsigned witness transaction stored in the stash by
+/// [`WalletProvider::transfer`] with the signed and finalized one from the
+/// `psbt`, keeping the anchors of the witness.
+///
+/// Contract state and index don't depend on the witness signatures, thus they
+/// are left untouched.
+#[allow(clippy::result_large_err)]
+pub fn finalize_witness<S: StashProvider>(
+    stash: &mut S,
+    psbt: &Psbt,
+) -> Result<(), CompletionError> {
+    let tx = psbt
+        .extract_signed_tx()
+        .ok_or(CompletionError::NotFinalized)?;
+    let witness_id = XWitnessId::Bitcoin(tx.txid());
+    let anchors = stash
+        .witness(witness_id)
+        .map_err(|e| e.to_string())?
+        .anchors
+        .clone();
+    let witness = SealWitness::new(XChain::Bitcoin(PubWitness::with(tx)), anchors);
+    stash.replace_witness(witness).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::ByteArray;
+    use bp::{LockTime, SeqNo, TxOut, TxVer, VarIntArray, Witness};
+    use psrgbt::{UnsignedTx, UnsignedTxIn};
+    use rgbstd::containers::AnchorSet;
+    use rgbstd::persistence::{MemStash, StashReadProvider, StashWriteProvider};
+    use strict_types::encoding::StrictDumb;
+
+    use super::*;
+
+    #[test]
+    fn finalize_replaces_witness_tx() {
+        let mut psbt = Psbt::from_tx(UnsignedTx {
+            version: TxVer::V2,
+            inputs: VarIntArray::try_from(vec![UnsignedTxIn {
+                prev_output: Outpoint::new(Txid::from_byte_array([0xAB; 32]), 0),
+                sequence: SeqNo::from_consensus_u32(0),
+            }])
+            .unwrap(),
+            outputs: VarIntArray::try_from(vec![TxOut::new(
+                ScriptPubkey::op_return(&[]),
+                Sats::ZERO,
+            )])
+            .unwrap(),
+            lock_time: LockTime::ZERO,
+        });
+        let unsigned = psbt.to_unsigned_tx().finalize();
+        let witness_id = XWitnessId::Bitcoin(unsigned.txid());
+        let anchors = AnchorSet::strict_dumb();
+        let mut stash = MemStash::default();
+        stash
+            .replace_witness(SealWitness::new(
+                XChain::Bitcoin(PubWitness::with(unsigned)),
+                anchors.clone(),
+            ))
+            .unwrap();
+
+        assert!(matches!(finalize_witness(&mut stash, &psbt), Err(CompletionError::NotFinalized)));
+
+        psbt.input_mut(0).unwrap().final_witness =
+            Some(Witness::from_consensus_stack(vec![vec![0x01; 64]]));
+        finalize_witness(&mut stash, &psbt).unwrap();
+
+        let witness = stash.witness(witness_id).unwrap();
+        assert_eq!(witness.anchors, anchors);
+        let XChain::Bitcoin(public) = &witness.public else {
+            panic!("bitcoin witness expected");
+        };
+        let tx = public.tx.as_ref().unwrap();
+        assert_eq!(tx.txid(), public.txid);
+        assert_eq!(tx.inputs[0].witness.elements().collect::<Vec<_>>(), vec![&[0x01; 64][..]]);
+    }
+}
@@ -22,20 +22,183 @@
 // limitations under the License.
 
 use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use bp::Tx;
-use bpstd::Network;
+use bp::{BlockHeader, Tx};
 use rgbstd::containers::Consignment;
 use rgbstd::resolvers::ResolveHeight;
 use rgbstd::validation::{ResolveWitness, WitnessResolverError};
 use rgbstd::{WitnessAnchor, XWitnessId, XWitnessTx};
 
+use super::{
+    CachePolicy, CachingResolver, CallOutcome, ChainParams, ObservedResolver, OfflineResolver,
+    QuorumError, QuorumResolver, ResolverCall, ResolverCounters, ResolverEvent, ResolverObserver,
+    RetryPolicy, RetryingResolver, SharedObserver, SpvError, SpvProof, SpvResolver, WitnessBundle,
+};
 use crate::{Txid, WitnessOrd, XChain};
 
+/// Status of a transaction as seen by the resolver backend.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
+pub enum TxStatus {
+    /// The transaction is unknown to the backend: it was never broadcast or
+    /// was evicted from the mempool.
+    #[display("unknown")]
+    Unknown,
+
+    /// The transaction is in the mempool.
+    #[display("in mempool")]
+    Mempool,
+
+    /// The transaction is mined at `height` and has the given number of
+    /// confirmations.
+    #[display("{confirmations} confirmations")]
+    Mined { height: u32, confirmations: u32 },
+}
+
+impl TxStatus {
+    pub fn confirmations(self) -> u32 {
+        match self {
+            TxStatus::Unknown | TxStatus::Mempool => 0,
+            TxStatus::Mined { confirmations, .. } => confirmations,
+        }
+    }
+}
+
+/// Error returned by [`RgbResolver`] requests.
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(inner)]
+pub enum ResolverError {
+    /// Error reported by the resolver backend which may be caused by a network
+    /// or server failure, such that repeating the request may succeed.
+    #[from]
+    Backend(String),
+
+    /// Error reported by the resolver backend which won't go away if the
+    /// request is repeated, like a misconfigured backend or invalid data
+    /// returned by it.
+    Permanent(String),
+
+    /// Resolver backends haven't reached the required agreement.
+    #[from]
+    Quorum(QuorumError),
+}
+
+impl ResolverError {
+    pub fn permanent(err: impl ToString) -> Self { ResolverError::Permanent(err.to_string()) }
+
+    /// Detects whether the error may be caused by a network or server failure,
+    /// in which case the request is worth retrying.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ResolverError::Backend(_) => true,
+            ResolverError::Permanent(_) => false,
+            // Quorum backends are wrapped into retrying resolvers on their own
+            ResolverError::Quorum(_) => false,
+        }
+    }
+}
+
+/// Error reported by [`RgbResolver::check`] when the resolver backend serves a
+/// network different from the one of the wallet.
+pub(crate) const NETWORK_MISMATCH: &str =
+    "resolver is for a network different from the wallet's one";
+
 pub trait RgbResolver {
-    fn check(&self, network: Network, expected_block_hash: String) -> Result<(), String>;
-    fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, String>;
-    fn resolve_pub_witness(&self, txid: Txid) -> Result<Tx, Option<String>>;
+    fn check(&self, chain: &ChainParams) -> Result<(), ResolverError>;
+    fn resolve_tip_height(&self) -> Result<u32, ResolverError>;
+    fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, ResolverError>;
+    fn resolve_pub_witness(&self, txid: Txid) -> Result<Tx, Option<ResolverError>>;
+
+    /// Resolves transaction status distinguishing transactions in mempool from
+    /// the ones unknown to the backend.
+    fn resolve_status(&mut self, txid: Txid) -> Result<TxStatus, ResolverError> {
+        if let WitnessOrd::OnChain(pos) = self.resolve_height(txid)?.witness_ord {
+            let height = u32::from(pos.height());
+            let confirmations = self.resolve_tip_height()?.saturating_sub(height) + 1;
+            return Ok(TxStatus::Mined {
+                height,
+                confirmations,
+            });
+        }
+        match self.resolve_pub_witness(txid) {
+            Ok(_) => Ok(TxStatus::Mempool),
+            Err(None) => Ok(TxStatus::Unknown),
+            Err(Some(err)) => Err(err),
+        }
+    }
+
+    /// Provides SPV proof for a transaction mined at the given `height`.
+    fn resolve_spv_proof(&self, _txid: Txid, _height: u32) -> Result<SpvProof, ResolverError> {
+        Err(ResolverError::permanent("resolver doesn't support SPV proofs"))
+    }
+
+    /// Provides headers of up to `count` blocks starting from the `start`
+    /// height. Fewer headers are returned if the chain tip is reached.
+    fn resolve_headers(&self, _start: u32, _count: u32) -> Result<Vec<BlockHeader>, ResolverError> {
+        Err(ResolverError::permanent("resolver doesn't provide block headers"))
+    }
+
+    /// Resolves mining status for multiple transactions, returning results in
+    /// the same order as `txids`.
+    ///
+    /// Default implementation resolves transactions one by one; backends
+    /// should override it if they are able to process batch requests.
+    fn resolve_heights(&mut self, txids: &[Txid]) -> Vec<Result<WitnessAnchor, ResolverError>> {
+        txids
+            .iter()
+            .map(|txid| self.resolve_height(*txid))
+            .collect()
+    }
+
+    /// Resolves multiple transactions, returning results in the same order as
+    /// `txids`.
+    ///
+    /// Default implementation resolves transactions one by one; backends
+    /// should override it if they are able to process batch requests.
+    fn resolve_pub_witnesses(&self, txids: &[Txid]) -> Vec<Result<Tx, Option<ResolverError>>> {
+        txids
+            .iter()
+            .map(|txid| self.resolve_pub_witness(*txid))
+            .collect()
+    }
+
+    /// Tells whether the `call` for `txid` will be answered from a local
+    /// cache without querying the backend.
+    fn is_cached(&self, _call: ResolverCall, _txid: Txid) -> bool { false }
+}
+
+impl<R: RgbResolver + ?Sized> RgbResolver for Box<R> {
+    fn check(&self, chain: &ChainParams) -> Result<(), ResolverError> { self.as_ref().check(chain) }
+    fn resolve_tip_height(&self) -> Result<u32, ResolverError> {
+        self.as_ref().resolve_tip_height()
+    }
+    fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, ResolverError> {
+        self.as_mut().resolve_height(txid)
+    }
+    fn resolve_pub_witness(&self, txid: Txid) -> Result<Tx, Option<ResolverError>> {
+        self.as_ref().resolve_pub_witness(txid)
+    }
+    fn resolve_status(&mut self, txid: Txid) -> Result<TxStatus, ResolverError> {
+        self.as_mut().resolve_status(txid)
+    }
+    fn resolve_spv_proof(&self, txid: Txid, height: u32) -> Result<SpvProof, ResolverError> {
+        self.as_ref().resolve_spv_proof(txid, height)
+    }
+    fn resolve_headers(&self, start: u32, count: u32) -> Result<Vec<BlockHeader>, ResolverError> {
+        self.as_ref().resolve_headers(start, count)
+    }
+    fn resolve_heights(&mut self, txids: &[Txid]) -> Vec<Result<WitnessAnchor, ResolverError>> {
+        self.as_mut().resolve_heights(txids)
+    }
+    fn resolve_pub_witnesses(&self, txids: &[Txid]) -> Vec<Result<Tx, Option<ResolverError>>> {
+        self.as_ref().resolve_pub_witnesses(txids)
+    }
+    fn is_cached(&self, call: ResolverCall, txid: Txid) -> bool {
+        self.as_ref().is_cached(call, txid)
+    }
 }
 
 /// Type that contains any of the [`Resolver`] types defined by the library
@@ -44,55 +207,363 @@ pub trait RgbResolver {
 pub struct AnyResolver {
     inner: Box<dyn RgbResolver>,
     terminal_txes: HashMap<Txid, Tx>,
+    witness_txes: HashMap<Txid, Tx>,
+    witness_anchors: HashMap<Txid, WitnessAnchor>,
+    backend: String,
+    counters: Arc<ResolverCounters>,
+    observer: SharedObserver,
 }
 
 impl AnyResolver {
+    fn new(backend: &str, inner: Box<dyn RgbResolver>) -> Self {
+        AnyResolver {
+            inner,
+            terminal_txes: empty!(),
+            witness_txes: empty!(),
+            witness_anchors: empty!(),
+            backend: backend.to_owned(),
+            counters: default!(),
+            observer: default!(),
+        }
+    }
+
     #[cfg(feature = "electrum_blocking")]
-    pub fn electrum_blocking(url: &str, config: Option<electrum::Config>) -> Result<Self, String> {
+    pub fn electrum_blocking(
+        url: &str,
+        config: Option<electrum::Config>,
+    ) -> Result<Self, ResolverError> {
+        let client = electrum::Client::from_config(url, config.unwrap_or_default())
+            .map_err(|e| e.to_string())?;
+        Ok(Self::new("electrum", Box::new(client)))
+    }
+
+    #[cfg(feature = "esplora_blocking")]
+    pub fn esplora_blocking(
+        url: &str,
+        config: Option<esplora::Config>,
+    ) -> Result<Self, ResolverError> {
+        let client = esplora::BlockingClient::from_config(url, config.unwrap_or_default())
+            .map_err(ResolverError::permanent)?;
+        Ok(Self::new("esplora", Box::new(client)))
+    }
+
+    #[cfg(feature = "bitcoind_rpc")]
+    pub fn bitcoind_rpc(
+        url: &str,
+        config: Option<super::bitcoind_rpc::Config>,
+    ) -> Result<Self, ResolverError> {
+        let client =
+            super::bitcoind_rpc::BitcoindClient::from_config(url, config.unwrap_or_default())
+                .map_err(ResolverError::permanent)?;
+        Ok(Self::new("bitcoind", Box::new(client)))
+    }
+
+    /// Constructs resolver using witness bundle file instead of network
+    /// requests.
+    pub fn offline(path: impl AsRef<Path>) -> Result<Self, ResolverError> {
+        let resolver = OfflineResolver::load(path).map_err(ResolverError::permanent)?;
+        Ok(Self::new("offline", Box::new(resolver)))
+    }
+
+    /// Constructs resolver requiring `threshold` of the named `backends` to
+    /// agree on all the returned data.
+    ///
+    /// Besides the requests made to the quorum, the observer of the resolver
+    /// is notified about the requests made to each of the backends, which are
+    /// reported under the backend names.
+    pub fn quorum(
+        threshold: usize,
+        backends: impl IntoIterator<Item = (String, AnyResolver)>,
+    ) -> Result<Self, ResolverError> {
+        let observer = SharedObserver::default();
+        let backends = backends.into_iter().map(|(name, resolver)| {
+            let member = ObservedResolver {
+                name: name.clone(),
+                inner: resolver.inner,
+                observer: observer.clone(),
+            };
+            (name, Box::new(member) as Box<dyn RgbResolver>)
+        });
+        let resolver = QuorumResolver::new(threshold, backends)?;
         Ok(AnyResolver {
-            inner: Box::new(
-                electrum::Client::from_config(url, config.unwrap_or_default())
-                    .map_err(|e| e.to_string())?,
-            ),
-            terminal_txes: Default::default(),
+            observer,
+            ..Self::new("quorum", Box::new(resolver))
         })
     }
 
-    #[cfg(feature = "esplora_blocking")]
-    pub fn esplora_blocking(url: &str, config: Option<esplora::Config>) -> Result<Self, String> {
+    /// Wraps the resolver into [`CachingResolver`] persisting its cache in
+    /// the `dir` directory.
+    pub fn with_cache(self, dir: impl AsRef<Path>, policy: CachePolicy) -> io::Result<Self> {
         Ok(AnyResolver {
-            inner: Box::new(
-                esplora::BlockingClient::from_config(url, config.unwrap_or_default())
-                    .map_err(|e| e.to_string())?,
-            ),
-            terminal_txes: Default::default(),
+            inner: Box::new(CachingResolver::load(dir, self.inner, policy)?),
+            ..self
         })
     }
 
-    pub fn check(&self, network: Network) -> Result<(), String> {
-        let expected_block_hash = match network {
-            Network::Mainnet => "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
-            Network::Testnet3 => "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943",
-            Network::Signet => "00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6",
-            Network::Regtest => "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+    /// Wraps the resolver into [`RetryingResolver`], retrying failed requests
+    /// according to the `policy`.
+    pub fn with_retry(self, policy: RetryPolicy) -> Self {
+        AnyResolver {
+            inner: Box::new(RetryingResolver::new(self.inner, policy)),
+            ..self
         }
-        .to_string();
-        self.inner.check(network, expected_block_hash)
+    }
+
+    /// Wraps the resolver into [`SpvResolver`], verifying SPV proofs for all
+    /// mined witness transactions against the header chain of `chain`.
+    pub fn with_spv(self, chain: &ChainParams) -> Result<Self, ResolverError> {
+        Ok(AnyResolver {
+            inner: Box::new(SpvResolver::new(self.inner, chain)?),
+            ..self
+        })
+    }
+
+    /// Sets observer which is notified about each request made by the
+    /// resolver, replacing the previously set one.
+    pub fn with_observer(self, observer: impl ResolverObserver + 'static) -> Self {
+        *self.observer.borrow_mut() = Some(Box::new(observer));
+        self
+    }
+
+    /// Returns counters of the requests made by the resolver.
+    ///
+    /// The counters are shared, so they may be kept after the resolver is
+    /// passed to the validation or consumed.
+    pub fn counters(&self) -> Arc<ResolverCounters> { self.counters.clone() }
+
+    fn report(
+        &self,
+        call: ResolverCall,
+        txid: Option<Txid>,
+        latency: Duration,
+        outcome: CallOutcome,
+    ) {
+        let event = ResolverEvent {
+            call,
+            txid,
+            backend: &self.backend,
+            latency,
+            outcome,
+        };
+        self.counters.on_call(&event);
+        if let Some(observer) = &*self.observer.borrow() {
+            observer.on_call(&event);
+        }
+    }
+
+    /// Tells for each of `txids` whether the `call` is answered from the
+    /// cache of the inner resolver.
+    fn cached(&self, call: ResolverCall, txids: &[Txid]) -> Vec<bool> {
+        txids
+            .iter()
+            .map(|txid| self.inner.is_cached(call, *txid))
+            .collect()
+    }
+
+    fn report_terminal_hit(&self, call: ResolverCall, txid: Txid) {
+        self.counters.on_terminal_hit(call, txid);
+        if let Some(observer) = &*self.observer.borrow() {
+            observer.on_terminal_hit(call, txid);
+        }
+    }
+
+    fn report_cache_hit(&self, call: ResolverCall, txid: Txid) {
+        self.counters.on_cache_hit(call, txid);
+        if let Some(observer) = &*self.observer.borrow() {
+            observer.on_cache_hit(call, txid);
+        }
+    }
+
+    /// Checks that the resolver is connected to the given chain, which can be
+    /// either one of the standard networks or custom [`ChainParams`].
+    pub fn check(&self, chain: impl Into<ChainParams>) -> Result<(), ResolverError> {
+        let started = Instant::now();
+        let res = self.inner.check(&chain.into());
+        self.report(ResolverCall::Check, None, started.elapsed(), CallOutcome::with(&res));
+        res
     }
 
     pub fn add_terminals<const TYPE: bool>(&mut self, consignment: &Consignment<TYPE>) {
-        self.terminal_txes.extend(
-            consignment
-                .bundles
-                .iter()
-                .filter_map(|bw| bw.pub_witness.maybe_map_ref(|w| w.tx.clone()))
-                .filter_map(|tx| match tx {
-                    XChain::Bitcoin(tx) => Some(tx),
-                    XChain::Liquid(_) | XChain::Other(_) => None,
-                })
-                .map(|tx| (tx.txid(), tx)),
-        );
+        self.terminal_txes.extend(terminal_txes(consignment));
+    }
+
+    pub fn resolve_tip_height(&self) -> Result<u32, ResolverError> {
+        let started = Instant::now();
+        let res = self.inner.resolve_tip_height();
+        let latency = started.elapsed();
+        self.report(ResolverCall::TipHeight, None, latency, CallOutcome::with(&res));
+        res
+    }
+
+    /// Resolves transaction status distinguishing transactions in mempool from
+    /// the ones unknown to the backend.
+    pub fn resolve_status(&mut self, txid: Txid) -> Result<TxStatus, ResolverError> {
+        let started = Instant::now();
+        let res = self.inner.resolve_status(txid);
+        let latency = started.elapsed();
+        self.report(ResolverCall::Status, Some(txid), latency, CallOutcome::with(&res));
+        res
+    }
+
+    /// Resolves status of all terminal witness transactions added with
+    /// [`AnyResolver::add_terminals`].
+    pub fn terminal_status(&mut self) -> Result<HashMap<Txid, TxStatus>, ResolverError> {
+        let txids = self.terminal_txes.keys().copied().collect::<Vec<_>>();
+        txids
+            .into_iter()
+            .map(|txid| Ok((txid, self.resolve_status(txid)?)))
+            .collect()
     }
+
+    /// Resolves all witness transactions of the consignment together with
+    /// their mining status using batch requests, such that the following
+    /// consignment validation doesn't need to query them one by one.
+    /// Transactions unknown to the backend are skipped and will be reported by
+    /// the validation as unresolved.
+    pub fn resolve_consignment<const TYPE: bool>(
+        &mut self,
+        consignment: &Consignment<TYPE>,
+    ) -> Result<(), ResolverError> {
+        let txids = consignment
+            .bundles
+            .iter()
+            .filter_map(|bw| match bw.witness_id() {
+                XWitnessId::Bitcoin(txid) => Some(txid),
+                _ => None,
+            })
+            .filter(|txid| !self.terminal_txes.contains_key(txid))
+            .collect::<Vec<_>>();
+
+        let missing = txids
+            .iter()
+            .filter(|txid| !self.witness_txes.contains_key(*txid))
+            .copied()
+            .collect::<Vec<_>>();
+        let cached = self.cached(ResolverCall::PubWitness, &missing);
+        let started = Instant::now();
+        let resolved = self.inner.resolve_pub_witnesses(&missing);
+        let latency = batch_latency(started, &cached);
+        for ((txid, res), cached) in missing.iter().zip(resolved).zip(cached) {
+            if cached {
+                self.report_cache_hit(ResolverCall::PubWitness, *txid);
+            } else {
+                let outcome = CallOutcome::with_unknown(&res);
+                self.report(ResolverCall::PubWitness, Some(*txid), latency, outcome);
+            }
+            match res {
+                Ok(tx) => {
+                    self.witness_txes.insert(*txid, tx);
+                }
+                Err(None) => continue,
+                Err(Some(err)) => return Err(err),
+            }
+        }
+
+        let missing = txids
+            .iter()
+            .filter(|txid| {
+                self.witness_txes.contains_key(*txid) && !self.witness_anchors.contains_key(*txid)
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        let cached = self.cached(ResolverCall::Height, &missing);
+        let started = Instant::now();
+        let resolved = self.inner.resolve_heights(&missing);
+        let latency = batch_latency(started, &cached);
+        for ((txid, res), cached) in missing.iter().zip(resolved).zip(cached) {
+            if cached {
+                self.report_cache_hit(ResolverCall::Height, *txid);
+            } else {
+                self.report(ResolverCall::Height, Some(*txid), latency, CallOutcome::with(&res));
+            }
+            self.witness_anchors.insert(*txid, res?);
+        }
+        Ok(())
+    }
+
+    /// Resolves all witness transactions of the consignment together with
+    /// their mining status, such that they can be saved and used later with
+    /// [`OfflineResolver`] on a machine without network access.
+    pub fn export_witnesses<const TYPE: bool>(
+        &mut self,
+        chain: impl Into<ChainParams>,
+        consignment: &Consignment<TYPE>,
+    ) -> Result<WitnessBundle, ResolverError> {
+        let mut bundle = WitnessBundle {
+            genesis_hash: Some(chain.into().genesis_hash),
+            tip_height: Some(self.resolve_tip_height()?),
+            ..default!()
+        };
+        for bw in &consignment.bundles {
+            let witness_id = bw.witness_id();
+            match self.resolve_pub_witness(witness_id) {
+                Ok(XWitnessTx::Bitcoin(tx)) => bundle.add_tx(tx),
+                Ok(_) | Err(WitnessResolverError::Unknown(_)) => continue,
+                Err(err) => return Err(err.to_string().into()),
+            }
+            let anchor = self.resolve_height(witness_id)?;
+            if let (XWitnessId::Bitcoin(txid), WitnessOrd::OnChain(pos)) =
+                (witness_id, anchor.witness_ord)
+            {
+                let started = Instant::now();
+                let res = self.inner.resolve_spv_proof(txid, u32::from(pos.height()));
+                let outcome = CallOutcome::with(&res);
+                self.report(ResolverCall::SpvProof, Some(txid), started.elapsed(), outcome);
+                if let Ok(proof) = res {
+                    bundle.proofs.insert(txid, proof);
+                }
+            }
+            bundle.add_anchor(anchor);
+        }
+        Ok(bundle)
+    }
+
+    /// Adds to the bundle the header chain starting from the SPV checkpoint of
+    /// the chain (or its genesis) up to the highest block mining a bundled
+    /// witness, such that the bundle can be validated by [`SpvResolver`].
+    pub fn export_headers(
+        &mut self,
+        chain: &ChainParams,
+        bundle: &mut WitnessBundle,
+    ) -> Result<(), ResolverError> {
+        let Some(last) = bundle.proofs.values().map(|proof| proof.height).max() else {
+            return Ok(());
+        };
+        let mut height = chain.checkpoint.as_ref().map_or(0, |checkpoint| checkpoint.height);
+        while height <= last {
+            let count = (last - height + 1).min(chain.pow.interval.max(1));
+            let headers = self.inner.resolve_headers(height, count)?;
+            if headers.is_empty() {
+                return Err(SpvError::MissingHeader(height).into());
+            }
+            for header in headers.into_iter().take(count as usize) {
+                bundle.headers.insert(height, header);
+                height += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Divides latency of a batch request among the transactions which were
+/// actually requested from the backend.
+fn batch_latency(started: Instant, cached: &[bool]) -> Duration {
+    let requested = cached.iter().filter(|cached| !**cached).count();
+    started.elapsed() / requested.max(1) as u32
+}
+
+pub(super) fn terminal_txes<const TYPE: bool>(
+    consignment: &Consignment<TYPE>,
+) -> impl Iterator<Item = (Txid, Tx)> + '_ {
+    consignment
+        .bundles
+        .iter()
+        .filter_map(|bw| bw.pub_witness.maybe_map_ref(|w| w.tx.clone()))
+        .filter_map(|tx| match tx {
+            XChain::Bitcoin(tx) => Some(tx),
+            XChain::Liquid(_) | XChain::Other(_) => None,
+        })
+        .map(|tx| (tx.txid(), tx))
 }
 
 impl ResolveHeight for AnyResolver {
@@ -102,13 +573,27 @@ impl ResolveHeight for AnyResolver {
         };
 
         if self.terminal_txes.contains_key(&txid) {
+            self.report_terminal_hit(ResolverCall::Height, txid);
             return Ok(WitnessAnchor {
                 witness_ord: WitnessOrd::OffChain,
                 witness_id,
             });
         }
+        if let Some(anchor) = self.witness_anchors.get(&txid) {
+            self.report_cache_hit(ResolverCall::Height, txid);
+            return Ok(*anchor);
+        }
+
+        if self.inner.is_cached(ResolverCall::Height, txid) {
+            self.report_cache_hit(ResolverCall::Height, txid);
+            return self.inner.resolve_height(txid).map_err(|e| e.to_string());
+        }
 
-        self.inner.resolve_height(txid)
+        let started = Instant::now();
+        let res = self.inner.resolve_height(txid);
+        let latency = started.elapsed();
+        self.report(ResolverCall::Height, Some(txid), latency, CallOutcome::with(&res));
+        res.map_err(|e| e.to_string())
     }
 }
 
@@ -125,15 +610,142 @@ impl ResolveWitness for AnyResolver {
         };
 
         if let Some(tx) = self.terminal_txes.get(&txid) {
+            self.report_terminal_hit(ResolverCall::PubWitness, txid);
+            return Ok(XWitnessTx::Bitcoin(tx.clone()));
+        }
+        if let Some(tx) = self.witness_txes.get(&txid) {
+            self.report_cache_hit(ResolverCall::PubWitness, txid);
             return Ok(XWitnessTx::Bitcoin(tx.clone()));
         }
 
-        self.inner
-            .resolve_pub_witness(txid)
-            .map(XWitnessTx::Bitcoin)
-            .map_err(|e| match e {
-                None => WitnessResolverError::Unknown(witness_id),
-                Some(e) => WitnessResolverError::Other(witness_id, e),
+        let res = if self.inner.is_cached(ResolverCall::PubWitness, txid) {
+            self.report_cache_hit(ResolverCall::PubWitness, txid);
+            self.inner.resolve_pub_witness(txid)
+        } else {
+            let started = Instant::now();
+            let res = self.inner.resolve_pub_witness(txid);
+            let outcome = CallOutcome::with_unknown(&res);
+            self.report(ResolverCall::PubWitness, Some(txid), started.elapsed(), outcome);
+            res
+        };
+        res.map(XWitnessTx::Bitcoin).map_err(|e| match e {
+            None => WitnessResolverError::Unknown(witness_id),
+            Some(e) => WitnessResolverError::Other(witness_id, e.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use amplify::ByteArray;
+
+    use super::*;
+    use crate::WitnessPos;
+
+    /// Backend which can't parse transactions with even first byte of the txid
+    /// and doesn't know the other ones, all of which are mined at height 100.
+    /// The tip height is unavailable.
+    struct Flaky;
+
+    impl RgbResolver for Flaky {
+        fn check(&self, _chain: &ChainParams) -> Result<(), ResolverError> { Ok(()) }
+        fn resolve_tip_height(&self) -> Result<u32, ResolverError> {
+            Err(s!("connection refused").into())
+        }
+        fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, ResolverError> {
+            Ok(WitnessAnchor {
+                witness_ord: WitnessOrd::OnChain(WitnessPos::new(100, 1231469665).unwrap()),
+                witness_id: XWitnessId::Bitcoin(txid),
             })
+        }
+        fn resolve_pub_witness(&self, txid: Txid) -> Result<Tx, Option<ResolverError>> {
+            match txid.to_byte_array()[0] % 2 {
+                0 => Err(Some(ResolverError::permanent("cannot deserialize raw TX"))),
+                _ => Err(None),
+            }
+        }
+    }
+
+    type Events = Rc<RefCell<Vec<(ResolverCall, Option<Txid>, String, CallOutcome)>>>;
+
+    struct Recorder(Events);
+
+    impl ResolverObserver for Recorder {
+        fn on_call(&self, event: &ResolverEvent) {
+            let backend = event.backend.to_owned();
+            self.0
+                .borrow_mut()
+                .push((event.call, event.txid, backend, event.outcome.clone()));
+        }
+    }
+
+    fn txid(n: u8) -> Txid { Txid::from_byte_array([n; 32]) }
+
+    #[test]
+    fn observer() {
+        let events = Events::default();
+        let mut resolver =
+            AnyResolver::new("flaky", Box::new(Flaky)).with_observer(Recorder(events.clone()));
+
+        resolver.check(ChainParams::mainnet()).unwrap();
+        resolver.resolve_tip_height().unwrap_err();
+        resolver.resolve_height(XWitnessId::Bitcoin(txid(1))).unwrap();
+        resolver.resolve_pub_witness(XWitnessId::Bitcoin(txid(1))).unwrap_err();
+        resolver.resolve_pub_witness(XWitnessId::Bitcoin(txid(2))).unwrap_err();
+
+        let error = CallOutcome::Error(s!("connection refused"));
+        let failure = CallOutcome::Error(s!("cannot deserialize raw TX"));
+        assert_eq!(*events.borrow(), vec![
+            (ResolverCall::Check, None, s!("flaky"), CallOutcome::Success),
+            (ResolverCall::TipHeight, None, s!("flaky"), error),
+            (ResolverCall::Height, Some(txid(1)), s!("flaky"), CallOutcome::Success),
+            (ResolverCall::PubWitness, Some(txid(1)), s!("flaky"), CallOutcome::NotFound),
+            (ResolverCall::PubWitness, Some(txid(2)), s!("flaky"), failure),
+        ]);
+
+        let counters = resolver.counters();
+        assert_eq!(counters.calls(), 5);
+        assert_eq!(counters.failures(), 2);
+        assert_eq!(counters.not_found(), 1);
+    }
+
+    #[test]
+    fn quorum_members() {
+        let events = Events::default();
+        let backends = ["a", "b"].map(|name| {
+            (name.to_owned(), AnyResolver::new("flaky", Box::new(Flaky)))
+        });
+        let resolver = AnyResolver::quorum(2, backends)
+            .unwrap()
+            .with_observer(Recorder(events.clone()));
+
+        resolver.check(ChainParams::mainnet()).unwrap();
+        let backends = events
+            .borrow()
+            .iter()
+            .map(|(call, _, backend, _)| (*call, backend.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(backends, vec![
+            (ResolverCall::Check, s!("a")),
+            (ResolverCall::Check, s!("b")),
+            (ResolverCall::Check, s!("quorum")),
+        ]);
+        // Requests to the members are not counted as the requests made by
+        // the resolver
+        assert_eq!(resolver.counters().calls(), 1);
+    }
+
+    #[test]
+    fn batch_latency_split() {
+        let started = Instant::now() - Duration::from_millis(300);
+        let latency = batch_latency(started, &[false, true, false, false]);
+        assert!(latency >= Duration::from_millis(100) && latency < Duration::from_millis(150));
+
+        let started = Instant::now() - Duration::from_millis(300);
+        let latency = batch_latency(started, &[true, true]);
+        assert!(latency >= Duration::from_millis(300));
     }
 }
@@ -0,0 +1,224 @@
+// RGB smart contracts for Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+
+use bp::Tx;
+use rgbstd::containers::Consignment;
+use rgbstd::resolvers::ResolveHeight;
+use rgbstd::validation::{ResolveWitness, WitnessResolverError};
+use rgbstd::{WitnessAnchor, XWitnessId, XWitnessTx};
+
+use super::any::terminal_txes;
+use super::ChainParams;
+use crate::{Txid, WitnessOrd};
+
+/// Future returned by [`AsyncRgbResolver`] methods.
+pub type ResolverFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+pub trait AsyncRgbResolver: Send + Sync {
+    fn check<'a>(&'a self, chain: &'a ChainParams) -> ResolverFuture<'a, Result<(), String>>;
+    fn resolve_height(&self, txid: Txid) -> ResolverFuture<'_, Result<WitnessAnchor, String>>;
+    fn resolve_pub_witness(&self, txid: Txid) -> ResolverFuture<'_, Result<Tx, Option<String>>>;
+}
+
+/// Asynchronous counterpart of [`crate::AnyResolver`].
+///
+/// Since consignment validation is synchronous, the resolver must be provided
+/// with all witness data beforehand using
+/// [`AnyAsyncResolver::resolve_consignment`]. After that it can be passed to
+/// `validate`, `accept_transfer` and other methods requiring [`ResolveWitness`]
+/// and [`ResolveHeight`] without blocking on network requests.
+#[derive(From)]
+#[non_exhaustive]
+pub struct AnyAsyncResolver {
+    inner: Box<dyn AsyncRgbResolver>,
+    terminal_txes: HashMap<Txid, Tx>,
+    witness_txes: HashMap<Txid, Tx>,
+    witness_anchors: HashMap<Txid, WitnessAnchor>,
+}
+
+impl AnyAsyncResolver {
+    #[cfg(feature = "electrum_async")]
+    pub fn electrum_async(url: &str, config: Option<electrum::Config>) -> Result<Self, String> {
+        Ok(AnyAsyncResolver {
+            inner: Box::new(super::electrum_async::AsyncClient::from_config(
+                url,
+                config.unwrap_or_default(),
+            )?),
+            terminal_txes: Default::default(),
+            witness_txes: Default::default(),
+            witness_anchors: Default::default(),
+        })
+    }
+
+    #[cfg(feature = "esplora_async")]
+    pub fn esplora_async(url: &str, config: Option<esplora::Config>) -> Result<Self, String> {
+        Ok(AnyAsyncResolver {
+            inner: Box::new(
+                esplora::AsyncClient::from_config(url, config.unwrap_or_default())
+                    .map_err(|e| e.to_string())?,
+            ),
+            terminal_txes: Default::default(),
+            witness_txes: Default::default(),
+            witness_anchors: Default::default(),
+        })
+    }
+
+    pub async fn check(&self, chain: impl Into<ChainParams>) -> Result<(), String> {
+        self.inner.check(&chain.into()).await
+    }
+
+    pub fn add_terminals<const TYPE: bool>(&mut self, consignment: &Consignment<TYPE>) {
+        self.terminal_txes.extend(terminal_txes(consignment));
+    }
+
+    /// Fetches all witness transactions of the consignment together with their
+    /// mining status. Transactions unknown to the backend are skipped and will
+    /// be reported by the validation as unresolved.
+    pub async fn resolve_consignment<const TYPE: bool>(
+        &mut self,
+        consignment: &Consignment<TYPE>,
+    ) -> Result<(), String> {
+        for bw in &consignment.bundles {
+            let XWitnessId::Bitcoin(txid) = bw.witness_id() else {
+                continue;
+            };
+            if self.terminal_txes.contains_key(&txid) {
+                continue;
+            }
+            if !self.witness_txes.contains_key(&txid) {
+                match self.inner.resolve_pub_witness(txid).await {
+                    Ok(tx) => {
+                        self.witness_txes.insert(txid, tx);
+                    }
+                    Err(None) => continue,
+                    Err(Some(err)) => return Err(err),
+                }
+            }
+            if !self.witness_anchors.contains_key(&txid) {
+                let anchor = self.inner.resolve_height(txid).await?;
+                self.witness_anchors.insert(txid, anchor);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ResolveHeight for AnyAsyncResolver {
+    fn resolve_height(&mut self, witness_id: XWitnessId) -> Result<WitnessAnchor, String> {
+        let XWitnessId::Bitcoin(txid) = witness_id else {
+            return Err(format!("{} is not supported as layer 1 network", witness_id.layer1()));
+        };
+
+        if self.terminal_txes.contains_key(&txid) {
+            return Ok(WitnessAnchor {
+                witness_ord: WitnessOrd::OffChain,
+                witness_id,
+            });
+        }
+
+        self.witness_anchors
+            .get(&txid)
+            .cloned()
+            .ok_or_else(|| format!("witness {witness_id} was not resolved in advance"))
+    }
+}
+
+impl ResolveWitness for AnyAsyncResolver {
+    fn resolve_pub_witness(
+        &self,
+        witness_id: XWitnessId,
+    ) -> Result<XWitnessTx, WitnessResolverError> {
+        let XWitnessId::Bitcoin(txid) = witness_id else {
+            return Err(WitnessResolverError::Other(
+                witness_id,
+                format!("{} is not supported as layer 1 network", witness_id.layer1()),
+            ));
+        };
+
+        self.terminal_txes
+            .get(&txid)
+            .or_else(|| self.witness_txes.get(&txid))
+            .cloned()
+            .map(XWitnessTx::Bitcoin)
+            .ok_or(WitnessResolverError::Unknown(witness_id))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::resolvers::mock::{block_on, MockServer};
+
+    #[test]
+    #[cfg(feature = "esplora_async")]
+    fn esplora_check() {
+        use crate::resolvers::mock::GENESIS_HASH;
+
+        let server = MockServer::http(|_, path, _| match path {
+            "/block-height/0" => (200, GENESIS_HASH.as_bytes().to_vec()),
+            _ => (404, vec![]),
+        });
+        let url = format!("http://{}", server.addr());
+        let resolver = AnyAsyncResolver::esplora_async(&url, None).unwrap();
+        block_on(resolver.check(ChainParams::mainnet())).unwrap();
+        assert!(block_on(resolver.check(ChainParams::testnet3())).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "electrum_async")]
+    fn electrum_check() {
+        use serde_json::json;
+
+        use crate::resolvers::mock::GENESIS_HEADER;
+
+        let server = MockServer::electrum(|method, _| match method {
+            "blockchain.block.header" => Ok(json!(GENESIS_HEADER)),
+            "blockchain.headers.subscribe" => Ok(json!({ "height": 0, "hex": GENESIS_HEADER })),
+            _ => Err(s!("genesis block coinbase is not considered an ordinary transaction")),
+        });
+        let url = format!("tcp://{}", server.addr());
+        let resolver = AnyAsyncResolver::electrum_async(&url, None).unwrap();
+        block_on(resolver.check(ChainParams::mainnet())).unwrap();
+        assert!(block_on(resolver.check(ChainParams::testnet3())).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "esplora_async")]
+    fn unresolved_witness() {
+        use std::str::FromStr;
+
+        use crate::resolvers::mock::GENESIS_TXID;
+
+        let server = MockServer::http(|_, _, _| (404, vec![]));
+        let url = format!("http://{}", server.addr());
+        let mut resolver = AnyAsyncResolver::esplora_async(&url, None).unwrap();
+        let witness_id = XWitnessId::Bitcoin(Txid::from_str(GENESIS_TXID).unwrap());
+        assert!(ResolveHeight::resolve_height(&mut resolver, witness_id).is_err());
+        assert!(matches!(
+            resolver.resolve_pub_witness(witness_id),
+            Err(WitnessResolverError::Unknown(id)) if id == witness_id
+        ));
+    }
+}
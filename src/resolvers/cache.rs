@@ -0,0 +1,392 @@
+// RGB smart contracts for Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use bp::{BlockHeader, Tx};
+use bpstd::Txid;
+use rgbstd::{WitnessAnchor, WitnessOrd};
+
+use super::{
+    ChainParams, ResolverCall, ResolverError, RgbResolver, SpvProof, TxStatus, WitnessBundle,
+};
+
+/// Policy for caching witness mining information.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct CachePolicy {
+    /// Minimal number of confirmations a witness transaction must have for its
+    /// mining information to be cached. Information about less-confirmed
+    /// transactions is always requested from the resolver since it may change
+    /// due to re-orgs.
+    pub min_depth: u32,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self { CachePolicy { min_depth: 6 } }
+}
+
+/// Resolver wrapper caching resolved witness transactions and their mining
+/// status on disk.
+///
+/// Transactions are cached forever, since they can't change; mining
+/// information is cached according to [`CachePolicy`]. Witnesses which are not
+/// mined are never cached.
+pub struct CachingResolver<R: RgbResolver> {
+    inner: R,
+    path: PathBuf,
+    policy: CachePolicy,
+    tip_height: Option<u32>,
+    cache: RefCell<WitnessBundle>,
+    dirty: Cell<bool>,
+}
+
+impl<R: RgbResolver> CachingResolver<R> {
+    pub const FILE_NAME: &'static str = "resolver.cache";
+
+    /// Loads cache from the `dir` directory; if the cache file is absent
+    /// starts with an empty cache.
+    pub fn load(dir: impl AsRef<Path>, inner: R, policy: CachePolicy) -> io::Result<Self> {
+        let path = dir.as_ref().join(Self::FILE_NAME);
+        let cache = match WitnessBundle::load(&path) {
+            Ok(cache) => cache,
+            Err(err) if err.kind() == ErrorKind::NotFound => WitnessBundle::default(),
+            Err(err) => return Err(err),
+        };
+        Ok(CachingResolver {
+            inner,
+            path,
+            policy,
+            tip_height: None,
+            cache: RefCell::new(cache),
+            dirty: Cell::new(false),
+        })
+    }
+
+    pub fn store(&self) -> io::Result<()> {
+        if !self.dirty.get() {
+            return Ok(());
+        }
+        // Cache is written into a temporary file first, such that an interrupted
+        // write never leaves a truncated cache behind
+        let tmp_path = self.path.with_extension("cache.tmp");
+        self.cache.borrow().save(&tmp_path)?;
+        fs::rename(&tmp_path, &self.path)?;
+        self.dirty.set(false);
+        Ok(())
+    }
+
+    /// Caches mining information of a witness if it is buried deep enough
+    /// according to the cache policy.
+    fn cache_anchor(&mut self, anchor: WitnessAnchor) -> Result<(), ResolverError> {
+        let WitnessOrd::OnChain(pos) = anchor.witness_ord else {
+            return Ok(());
+        };
+        let tip_height = match self.tip_height {
+            Some(height) => height,
+            None => {
+                let height = self.inner.resolve_tip_height()?;
+                self.tip_height = Some(height);
+                height
+            }
+        };
+        let depth = tip_height.saturating_sub(u32::from(pos.height())) + 1;
+        if depth >= self.policy.min_depth {
+            self.cache.get_mut().add_anchor(anchor);
+            self.dirty.set(true);
+        }
+        Ok(())
+    }
+}
+
+impl<R: RgbResolver> RgbResolver for CachingResolver<R> {
+    fn check(&self, chain: &ChainParams) -> Result<(), ResolverError> { self.inner.check(chain) }
+
+    fn resolve_tip_height(&self) -> Result<u32, ResolverError> { self.inner.resolve_tip_height() }
+
+    fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, ResolverError> {
+        if let Some(anchor) = self.cache.borrow().anchors.get(&txid) {
+            return Ok(*anchor);
+        }
+        let anchor = self.inner.resolve_height(txid)?;
+        self.cache_anchor(anchor)?;
+        Ok(anchor)
+    }
+
+    fn resolve_pub_witness(&self, txid: Txid) -> Result<Tx, Option<ResolverError>> {
+        if let Some(tx) = self.cache.borrow().txes.get(&txid) {
+            return Ok(tx.clone());
+        }
+        let tx = self.inner.resolve_pub_witness(txid)?;
+        self.cache.borrow_mut().add_tx(tx.clone());
+        self.dirty.set(true);
+        Ok(tx)
+    }
+
+    fn resolve_status(&mut self, txid: Txid) -> Result<TxStatus, ResolverError> {
+        // A cached transaction may have been evicted from the mempool since,
+        // thus the status is always requested from the resolver
+        self.inner.resolve_status(txid)
+    }
+
+    fn resolve_heights(&mut self, txids: &[Txid]) -> Vec<Result<WitnessAnchor, ResolverError>> {
+        let missing = txids
+            .iter()
+            .filter(|txid| !self.cache.get_mut().anchors.contains_key(*txid))
+            .copied()
+            .collect::<Vec<_>>();
+        let mut resolved = missing
+            .iter()
+            .copied()
+            .zip(self.inner.resolve_heights(&missing))
+            .collect::<HashMap<_, _>>();
+        txids
+            .iter()
+            .map(|txid| {
+                if let Some(anchor) = self.cache.get_mut().anchors.get(txid) {
+                    return Ok(*anchor);
+                }
+                let anchor = resolved
+                    .remove(txid)
+                    .unwrap_or_else(|| self.inner.resolve_height(*txid))?;
+                self.cache_anchor(anchor)?;
+                Ok(anchor)
+            })
+            .collect()
+    }
+
+    fn resolve_pub_witnesses(&self, txids: &[Txid]) -> Vec<Result<Tx, Option<ResolverError>>> {
+        let missing = txids
+            .iter()
+            .filter(|txid| !self.cache.borrow().txes.contains_key(*txid))
+            .copied()
+            .collect::<Vec<_>>();
+        let mut resolved = missing
+            .iter()
+            .copied()
+            .zip(self.inner.resolve_pub_witnesses(&missing))
+            .collect::<HashMap<_, _>>();
+        let mut cache = self.cache.borrow_mut();
+        txids
+            .iter()
+            .map(|txid| {
+                if let Some(tx) = cache.txes.get(txid) {
+                    return Ok(tx.clone());
+                }
+                let res = resolved.remove(txid).unwrap_or(Err(None));
+                if let Ok(tx) = &res {
+                    cache.add_tx(tx.clone());
+                    self.dirty.set(true);
+                }
+                res
+            })
+            .collect()
+    }
+
+    fn resolve_spv_proof(&self, txid: Txid, height: u32) -> Result<SpvProof, ResolverError> {
+        self.inner.resolve_spv_proof(txid, height)
+    }
+
+    fn resolve_headers(&self, start: u32, count: u32) -> Result<Vec<BlockHeader>, ResolverError> {
+        self.inner.resolve_headers(start, count)
+    }
+
+    fn is_cached(&self, call: ResolverCall, txid: Txid) -> bool {
+        let cache = self.cache.borrow();
+        match call {
+            ResolverCall::Height => cache.anchors.contains_key(&txid),
+            ResolverCall::PubWitness => cache.txes.contains_key(&txid),
+            _ => self.inner.is_cached(call, txid),
+        }
+    }
+}
+
+impl<R: RgbResolver> Drop for CachingResolver<R> {
+    fn drop(&mut self) {
+        // Failing to persist the cache must not affect the rest of the application
+        if let Err(_err) = self.store() {
+            #[cfg(feature = "log")]
+            log::warn!("unable to persist resolver cache to '{}': {_err}", self.path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, process};
+
+    use amplify::ByteArray;
+    use strict_types::encoding::StrictDumb;
+
+    use super::*;
+    use crate::{WitnessPos, XWitnessId};
+
+    /// Resolver for which a previously resolved transaction has been evicted
+    /// from the mempool.
+    struct Evicted;
+
+    impl RgbResolver for Evicted {
+        fn check(&self, _chain: &ChainParams) -> Result<(), ResolverError> { Ok(()) }
+        fn resolve_tip_height(&self) -> Result<u32, ResolverError> { Ok(100) }
+        fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, ResolverError> {
+            Ok(WitnessAnchor {
+                witness_ord: WitnessOrd::OffChain,
+                witness_id: XWitnessId::Bitcoin(txid),
+            })
+        }
+        fn resolve_pub_witness(&self, _txid: Txid) -> Result<Tx, Option<ResolverError>> {
+            Err(None)
+        }
+    }
+
+    /// Resolver for which transaction `[n; 32]` is mined at height `n`, with
+    /// the tip at height 100; transactions with `n` of zero are not mined.
+    #[derive(Default)]
+    struct Mined {
+        requested: Vec<Txid>,
+        batches: usize,
+    }
+
+    impl RgbResolver for Mined {
+        fn check(&self, _chain: &ChainParams) -> Result<(), ResolverError> { Ok(()) }
+        fn resolve_tip_height(&self) -> Result<u32, ResolverError> { Ok(100) }
+        fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, ResolverError> {
+            self.requested.push(txid);
+            let witness_ord = match txid.to_byte_array()[0] {
+                0 => WitnessOrd::OffChain,
+                height => WitnessOrd::OnChain(WitnessPos::new(height as u32, 1296688602).unwrap()),
+            };
+            Ok(WitnessAnchor {
+                witness_ord,
+                witness_id: XWitnessId::Bitcoin(txid),
+            })
+        }
+        fn resolve_pub_witness(&self, _txid: Txid) -> Result<Tx, Option<ResolverError>> {
+            Err(None)
+        }
+        fn resolve_heights(&mut self, txids: &[Txid]) -> Vec<Result<WitnessAnchor, ResolverError>> {
+            self.batches += 1;
+            txids.iter().map(|txid| self.resolve_height(*txid)).collect()
+        }
+    }
+
+    fn txid(n: u8) -> Txid { Txid::from_byte_array([n; 32]) }
+
+    #[test]
+    fn min_depth() {
+        let dir = env::temp_dir().join(format!("rgb-resolver-depth-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let policy = CachePolicy { min_depth: 6 };
+        let mut resolver = CachingResolver::load(&dir, Mined::default(), policy).unwrap();
+        for n in [95, 96, 0] {
+            resolver.resolve_height(txid(n)).unwrap();
+        }
+        // Only the transaction with six confirmations is deep enough
+        assert!(resolver.is_cached(ResolverCall::Height, txid(95)));
+        assert!(!resolver.is_cached(ResolverCall::Height, txid(96)));
+        assert!(!resolver.is_cached(ResolverCall::Height, txid(0)));
+
+        resolver.dirty.set(false);
+        drop(resolver);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn store_reload() {
+        let dir = env::temp_dir().join(format!("rgb-resolver-reload-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut resolver = CachingResolver::load(&dir, Mined::default(), default!()).unwrap();
+        let anchor = resolver.resolve_height(txid(1)).unwrap();
+        resolver.store().unwrap();
+        drop(resolver);
+
+        let mut resolver = CachingResolver::load(&dir, Mined::default(), default!()).unwrap();
+        assert!(resolver.is_cached(ResolverCall::Height, txid(1)));
+        assert_eq!(resolver.resolve_height(txid(1)), Ok(anchor));
+        assert!(resolver.inner.requested.is_empty());
+
+        drop(resolver);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn batch_heights() {
+        let dir = env::temp_dir().join(format!("rgb-resolver-batch-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut resolver = CachingResolver::load(&dir, Mined::default(), default!()).unwrap();
+        resolver.resolve_height(txid(1)).unwrap();
+        resolver.inner.requested.clear();
+
+        let txids = [txid(1), txid(2), txid(0)];
+        let expected = txids
+            .iter()
+            .map(|txid| Mined::default().resolve_height(*txid))
+            .collect::<Vec<_>>();
+        assert_eq!(resolver.resolve_heights(&txids), expected);
+        // Cached transaction is not requested, the rest are requested in a single batch
+        assert_eq!(resolver.inner.batches, 1);
+        assert_eq!(resolver.inner.requested, vec![txid(2), txid(0)]);
+
+        resolver.dirty.set(false);
+        drop(resolver);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn status_bypasses_cache() {
+        let dir = env::temp_dir().join(format!("rgb-resolver-cache-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut resolver = CachingResolver::load(&dir, Evicted, default!()).unwrap();
+        let tx = Tx::strict_dumb();
+        let txid = tx.txid();
+        resolver.cache.get_mut().add_tx(tx);
+
+        assert!(resolver.resolve_pub_witness(txid).is_ok());
+        assert_eq!(resolver.resolve_status(txid), Ok(TxStatus::Unknown));
+
+        resolver.dirty.set(false);
+        drop(resolver);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cache_hits() {
+        let dir = env::temp_dir().join(format!("rgb-resolver-hits-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut resolver = CachingResolver::load(&dir, Evicted, default!()).unwrap();
+        let tx = Tx::strict_dumb();
+        let txid = tx.txid();
+        assert!(!resolver.is_cached(ResolverCall::PubWitness, txid));
+        resolver.cache.get_mut().add_tx(tx);
+
+        assert!(resolver.is_cached(ResolverCall::PubWitness, txid));
+        // Anchors of unmined transactions are never cached
+        resolver.resolve_height(txid).unwrap();
+        assert!(!resolver.is_cached(ResolverCall::Height, txid));
+
+        resolver.dirty.set(false);
+        drop(resolver);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
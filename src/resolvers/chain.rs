@@ -0,0 +1,229 @@
+// RGB smart contracts for Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bpstd::Network;
+
+/// Parameters of the blockchain which resolvers must be connected to.
+///
+/// Unlike [`Network`], which covers only the standard networks, this allows
+/// using resolvers with testnet4, custom signets (like mutinynet) and other
+/// bitcoin-compatible chains.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct ChainParams {
+    /// Human-readable name of the chain.
+    pub name: String,
+    /// Hash of the chain genesis block, in the standard hex representation.
+    pub genesis_hash: String,
+    /// Whether the chain is a test network.
+    pub testnet: bool,
+    /// Proof-of-work rules of the chain, used by SPV verification of block
+    /// headers. Defaults to the mainnet rules.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pow: PowParams,
+    /// Block from which SPV verification starts validating block headers. If
+    /// not given, the headers are validated starting from the genesis block.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub checkpoint: Option<Checkpoint>,
+}
+
+impl ChainParams {
+    pub fn mainnet() -> Self {
+        ChainParams {
+            name: s!("mainnet"),
+            genesis_hash: s!("000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"),
+            testnet: false,
+            pow: PowParams::mainnet(),
+            checkpoint: None,
+        }
+    }
+
+    pub fn testnet3() -> Self {
+        ChainParams {
+            name: s!("testnet3"),
+            genesis_hash: s!("000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943"),
+            testnet: true,
+            pow: PowParams::testnet3(),
+            checkpoint: None,
+        }
+    }
+
+    pub fn testnet4() -> Self {
+        ChainParams {
+            name: s!("testnet4"),
+            genesis_hash: s!("00000000da84f2bafbbc53dee25a72ae507ff4914b867c565be350b0da8bf043"),
+            testnet: true,
+            pow: PowParams::testnet4(),
+            checkpoint: None,
+        }
+    }
+
+    /// Default signet. Custom signets share the same genesis block, so this
+    /// can be used with any of them by changing the name.
+    pub fn signet() -> Self {
+        ChainParams {
+            name: s!("signet"),
+            genesis_hash: s!("00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6"),
+            testnet: true,
+            pow: PowParams::signet(),
+            checkpoint: None,
+        }
+    }
+
+    pub fn mutinynet() -> Self {
+        ChainParams {
+            name: s!("mutinynet"),
+            ..Self::signet()
+        }
+    }
+
+    pub fn regtest() -> Self {
+        ChainParams {
+            name: s!("regtest"),
+            genesis_hash: s!("0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206"),
+            testnet: true,
+            pow: PowParams::regtest(),
+            checkpoint: None,
+        }
+    }
+
+    /// Returns parameters of one of the chains known to the library by its
+    /// name.
+    pub fn with_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "mainnet" | "bitcoin" => Self::mainnet(),
+            "testnet" | "testnet3" => Self::testnet3(),
+            "testnet4" => Self::testnet4(),
+            "signet" => Self::signet(),
+            "mutinynet" => Self::mutinynet(),
+            "regtest" => Self::regtest(),
+            _ => return None,
+        })
+    }
+}
+
+impl From<Network> for ChainParams {
+    fn from(network: Network) -> Self {
+        match network {
+            Network::Mainnet => Self::mainnet(),
+            Network::Testnet3 => Self::testnet3(),
+            Network::Signet => Self::signet(),
+            Network::Regtest => Self::regtest(),
+        }
+    }
+}
+
+/// Proof-of-work consensus rules used to validate difficulty targets of block
+/// headers.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct PowParams {
+    /// Easiest allowed difficulty target, in the compact form.
+    pub limit: u32,
+    /// Number of blocks between difficulty adjustments.
+    pub interval: u32,
+    /// Expected time between two blocks, in seconds.
+    pub spacing: u32,
+    /// Whether the difficulty is adjusted at all.
+    pub retargeting: bool,
+    /// Whether a block mined later than two block intervals after the previous
+    /// one may use the easiest target, as it is allowed on test networks.
+    pub min_difficulty_blocks: bool,
+    /// Whether BIP-94 rules (testnet4) are enforced.
+    pub bip94: bool,
+}
+
+impl Default for PowParams {
+    fn default() -> Self { PowParams::mainnet() }
+}
+
+impl PowParams {
+    pub fn mainnet() -> Self {
+        PowParams {
+            limit: 0x1d00ffff,
+            interval: 2016,
+            spacing: 600,
+            retargeting: true,
+            min_difficulty_blocks: false,
+            bip94: false,
+        }
+    }
+
+    pub fn testnet3() -> Self {
+        PowParams {
+            min_difficulty_blocks: true,
+            ..Self::mainnet()
+        }
+    }
+
+    pub fn testnet4() -> Self {
+        PowParams {
+            min_difficulty_blocks: true,
+            bip94: true,
+            ..Self::mainnet()
+        }
+    }
+
+    pub fn signet() -> Self {
+        PowParams {
+            limit: 0x1e0377ae,
+            ..Self::mainnet()
+        }
+    }
+
+    pub fn regtest() -> Self {
+        PowParams {
+            limit: 0x207fffff,
+            retargeting: false,
+            min_difficulty_blocks: true,
+            ..Self::mainnet()
+        }
+    }
+
+    /// Expected duration of the period between two difficulty adjustments, in
+    /// seconds.
+    pub fn timespan(&self) -> u32 { self.interval * self.spacing }
+}
+
+/// Block trusted by SPV verification without validating its ancestors.
+///
+/// The checkpoint must be the first block of a difficulty adjustment period,
+/// such that the difficulty of the following blocks can be validated.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+pub struct Checkpoint {
+    /// Height of the block.
+    pub height: u32,
+    /// Hash of the block, in the standard hex representation.
+    pub hash: String,
+}
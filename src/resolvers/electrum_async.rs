@@ -0,0 +1,161 @@
+// RGB smart contracts for Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Electrum protocol client has no native async implementation, thus this
+//! resolver runs the blocking client on a dedicated tokio thread pool.
+
+use std::sync::{Arc, Mutex};
+
+use bp::Tx;
+use bpstd::Txid;
+use electrum::{Client, Config};
+use rgbstd::WitnessAnchor;
+use tokio::task;
+
+use super::{AsyncRgbResolver, ChainParams, ResolverFuture, RgbResolver};
+
+#[derive(Clone)]
+pub struct AsyncClient(Arc<Mutex<Client>>);
+
+impl AsyncClient {
+    pub fn from_config(url: &str, config: Config) -> Result<Self, String> {
+        let client = Client::from_config(url, config).map_err(|e| e.to_string())?;
+        Ok(AsyncClient(Arc::new(Mutex::new(client))))
+    }
+
+    async fn with_client<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Client) -> T + Send + 'static,
+    ) -> Result<T, String> {
+        let client = self.0.clone();
+        task::spawn_blocking(move || {
+            let mut client = client.lock().expect("poisoned electrum client lock");
+            f(&mut client)
+        })
+        .await
+        .map_err(|e| e.to_string())
+    }
+}
+
+impl AsyncRgbResolver for AsyncClient {
+    fn check<'a>(&'a self, chain: &'a ChainParams) -> ResolverFuture<'a, Result<(), String>> {
+        let chain = chain.clone();
+        Box::pin(async move {
+            self.with_client(move |client| client.check(&chain).map_err(|e| e.to_string()))
+                .await?
+        })
+    }
+
+    fn resolve_height(&self, txid: Txid) -> ResolverFuture<'_, Result<WitnessAnchor, String>> {
+        Box::pin(async move {
+            self.with_client(move |client| client.resolve_height(txid).map_err(|e| e.to_string()))
+                .await?
+        })
+    }
+
+    fn resolve_pub_witness(&self, txid: Txid) -> ResolverFuture<'_, Result<Tx, Option<String>>> {
+        Box::pin(async move {
+            self.with_client(move |client| {
+                client
+                    .resolve_pub_witness(txid)
+                    .map_err(|e| e.map(|e| e.to_string()))
+            })
+            .await
+                .map_err(Some)?
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use rgbstd::{WitnessOrd, WitnessPos};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::resolvers::mock::{block_on, MockServer, GENESIS_HEADER, GENESIS_TX, GENESIS_TXID};
+    use crate::resolvers::NETWORK_MISMATCH;
+
+    const MEMPOOL_TXID: &str = "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+
+    fn electrum() -> (MockServer, AsyncClient) {
+        let server = MockServer::electrum(|method, params| {
+            let txid = params.first().and_then(Value::as_str).unwrap_or_default();
+            let verbose = params.get(1).and_then(Value::as_bool).unwrap_or_default();
+            match (method, txid) {
+                ("blockchain.block.header", _) => Ok(json!(GENESIS_HEADER)),
+                ("blockchain.headers.subscribe", _) => {
+                    Ok(json!({ "height": 100, "hex": GENESIS_HEADER }))
+                }
+                ("blockchain.transaction.get", GENESIS_TXID) if verbose => {
+                    Ok(json!({ "txid": txid, "confirmations": 1, "blocktime": 1231469665 }))
+                }
+                ("blockchain.transaction.get", GENESIS_TXID) => Ok(json!(GENESIS_TX)),
+                ("blockchain.transaction.get", MEMPOOL_TXID) if verbose => {
+                    Ok(json!({ "txid": txid }))
+                }
+                ("blockchain.transaction.get_merkle", _) => {
+                    Ok(json!({ "block_height": params[1], "merkle": [], "pos": 0 }))
+                }
+                _ => Err(s!("No such mempool or blockchain transaction")),
+            }
+        });
+        let url = format!("tcp://{}", server.addr());
+        let client = AsyncClient::from_config(&url, Config::default()).unwrap();
+        (server, client)
+    }
+
+    #[test]
+    fn check_network() {
+        let (_server, client) = electrum();
+        block_on(client.check(&ChainParams::mainnet())).unwrap();
+        assert_eq!(
+            block_on(client.check(&ChainParams::regtest())),
+            Err(NETWORK_MISMATCH.to_owned())
+        );
+    }
+
+    #[test]
+    fn resolve_witness() {
+        let (_server, client) = electrum();
+        let txid = Txid::from_str(GENESIS_TXID).unwrap();
+        let tx = block_on(client.resolve_pub_witness(txid)).unwrap();
+        assert_eq!(tx.txid(), txid);
+        assert_eq!(block_on(client.resolve_pub_witness(Txid::coinbase())), Err(None));
+    }
+
+    #[test]
+    fn resolve_height() {
+        let (_server, client) = electrum();
+        let txid = Txid::from_str(GENESIS_TXID).unwrap();
+        let anchor = block_on(client.resolve_height(txid)).unwrap();
+        assert_eq!(
+            anchor.witness_ord,
+            WitnessOrd::OnChain(WitnessPos::new(100, 1231469665).unwrap())
+        );
+        let txid = Txid::from_str(MEMPOOL_TXID).unwrap();
+        let anchor = block_on(client.resolve_height(txid)).unwrap();
+        assert_eq!(anchor.witness_ord, WitnessOrd::OffChain);
+        let anchor = block_on(client.resolve_height(Txid::coinbase())).unwrap();
+        assert_eq!(anchor.witness_ord, WitnessOrd::OffChain);
+    }
+}
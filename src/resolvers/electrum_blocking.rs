@@ -21,36 +21,55 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
 use std::iter;
 
 use bp::ConsensusDecode;
-use bpstd::{Network, Tx, Txid};
-use electrum::{Client, ElectrumApi, Error, Param};
+use bpstd::{BlockHeader, Tx, Txid};
+use electrum::{Batch, Client, ElectrumApi, Error, Param};
 use rgbstd::{WitnessAnchor, WitnessOrd, WitnessPos, XWitnessId};
 
-use super::RgbResolver;
+use super::{ChainParams, ResolverError, RgbResolver, SpvProof, TxStatus, NETWORK_MISMATCH};
+
+/// Error reported by [`RgbResolver::check`] when the Electrum server can't
+/// provide verbose transactions.
+const NO_VERBOSE_TX: &str =
+    "verbose transactions are unsupported by the provided electrum service";
 
 macro_rules! check {
     ($e:expr) => {
-        $e.map_err(|e| e.to_string())?
+        $e.map_err(ResolverError::from)?
     };
 }
 
+impl From<Error> for ResolverError {
+    fn from(err: Error) -> Self {
+        match err {
+            // Connection failures
+            Error::IOError(_) |
+            Error::SharedIOError(_) |
+            Error::AllAttemptsErrored(_) |
+            Error::CouldntLockReader |
+            Error::Mpsc => ResolverError::Backend(err.to_string()),
+            err => ResolverError::permanent(err),
+        }
+    }
+}
+
 impl RgbResolver for Client {
-    fn check(&self, network: Network, expected_block_hash: String) -> Result<(), String> {
+    fn check(&self, chain: &ChainParams) -> Result<(), ResolverError> {
         // check the electrum server is for the correct network
         let block_hash = check!(self.block_header(0)).block_hash().to_string();
-        if expected_block_hash != block_hash {
-            return Err(s!("resolver is for a network different from the wallet's one"));
+        if chain.genesis_hash != block_hash {
+            return Err(ResolverError::permanent(NETWORK_MISMATCH));
         }
         // check the electrum server has the required functionality (verbose
-        // transactions)
-        let txid = match network {
-            Network::Mainnet => "33e794d097969002ee05d336686fc03c9e15a597c1b9827669460fac98799036",
-            Network::Testnet3 => "5e6560fd518aadbed67ee4a55bdc09f19e619544f5511e9343ebba66d2f62653",
-            Network::Signet => "8153034f45e695453250a8fb7225a5e545144071d8ed7b0d3211efa1f3c92ad8",
-            Network::Regtest => "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
-        };
+        // transactions). The first block after genesis contains just a
+        // coinbase transaction, thus its merkle root is the coinbase txid. On
+        // a chain without blocks we fall back to the genesis coinbase, which
+        // can't be retrieved.
+        let height = check!(self.block_headers_subscribe()).height.min(1);
+        let txid = check!(self.block_header(height)).merkle_root;
         if let Err(e) = self.raw_call("blockchain.transaction.get", vec![
             Param::String(txid.to_string()),
             Param::Bool(true),
@@ -59,15 +78,19 @@ impl RgbResolver for Client {
                 .to_string()
                 .contains("genesis block coinbase is not considered an ordinary transaction")
             {
-                return Err(s!(
-                    "verbose transactions are unsupported by the provided electrum service"
-                ));
+                return Err(ResolverError::permanent(NO_VERBOSE_TX));
             }
         }
         Ok(())
     }
 
-    fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, String> {
+    fn resolve_tip_height(&self) -> Result<u32, ResolverError> {
+        let header = check!(self.block_headers_subscribe());
+        u32::try_from(header.height)
+            .map_err(|_| ResolverError::permanent("impossible height value"))
+    }
+
+    fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, ResolverError> {
         let mut witness_anchor = WitnessAnchor {
             witness_ord: WitnessOrd::OffChain,
             witness_id: XWitnessId::Bitcoin(txid),
@@ -88,7 +111,7 @@ impl RgbResolver for Client {
             {
                 return Ok(witness_anchor);
             }
-            Err(e) => return Err(e.to_string()),
+            Err(e) => return Err(e.into()),
             Ok(v) => v,
         };
         let forward = iter::from_fn(|| self.block_headers_pop().ok().flatten()).count() as isize;
@@ -109,7 +132,8 @@ impl RgbResolver for Client {
                 .ok_or(Error::InvalidResponse(tx_details.clone()))
         );
 
-        let tip_height = u32::try_from(header.height).map_err(|_| s!("impossible height value"))?;
+        let tip_height = u32::try_from(header.height)
+            .map_err(|_| ResolverError::permanent("impossible height value"))?;
         let height: isize = (tip_height - confirmations) as isize;
         const SAFETY_MARGIN: isize = 1;
         // first check from expected min to max height
@@ -118,10 +142,12 @@ impl RgbResolver for Client {
             // since this have a very low probability we do that after everything else
             .chain((1..=SAFETY_MARGIN).flat_map(|i| [i + forward + 1, 1 - i]))
             .find_map(|offset| self.transaction_get_merkle(&txid, (height + offset) as usize).ok())
-            .ok_or_else(|| s!("transaction can't be located in the blockchain"))?;
+            .ok_or_else(|| {
+                ResolverError::permanent("transaction can't be located in the blockchain")
+            })?;
 
         let tx_height = u32::try_from(get_merkle_res.block_height)
-            .map_err(|_| s!("impossible height value"))?;
+            .map_err(|_| ResolverError::permanent("impossible height value"))?;
 
         let pos = check!(
             WitnessPos::new(tx_height, block_time)
@@ -132,15 +158,164 @@ impl RgbResolver for Client {
         Ok(witness_anchor)
     }
 
-    fn resolve_pub_witness(&self, txid: Txid) -> Result<Tx, Option<String>> {
+    fn resolve_status(&mut self, txid: Txid) -> Result<TxStatus, ResolverError> {
+        let tx_details = match self.raw_call("blockchain.transaction.get", vec![
+            Param::String(txid.to_string()),
+            Param::Bool(true),
+        ]) {
+            Err(e)
+                if e.to_string()
+                    .contains("No such mempool or blockchain transaction") =>
+            {
+                return Ok(TxStatus::Unknown);
+            }
+            Err(e) => return Err(e.into()),
+            Ok(v) => v,
+        };
+        let confirmations = match tx_details.get("confirmations") {
+            None => 0,
+            Some(confirmations) => check!(
+                confirmations
+                    .as_u64()
+                    .and_then(|x| u32::try_from(x).ok())
+                    .ok_or(Error::InvalidResponse(tx_details.clone()))
+            ),
+        };
+        if confirmations == 0 {
+            return Ok(TxStatus::Mempool);
+        }
+        let tip_height = self.resolve_tip_height()?;
+        Ok(TxStatus::Mined {
+            height: (tip_height + 1).saturating_sub(confirmations),
+            confirmations,
+        })
+    }
+
+    fn resolve_pub_witness(&self, txid: Txid) -> Result<Tx, Option<ResolverError>> {
         let raw_tx = self.transaction_get_raw(&txid).map_err(|e| {
-            let e = e.to_string();
-            if e.contains("No such mempool or blockchain transaction") {
+            if e.to_string().contains("No such mempool or blockchain transaction") {
                 return None;
             }
-            Some(e)
+            Some(e.into())
         })?;
-        Tx::consensus_deserialize(raw_tx)
-            .map_err(|e| Some(format!("cannot deserialize raw TX - {e}")))
+        Tx::consensus_deserialize(raw_tx).map_err(|e| {
+            Some(ResolverError::permanent(format!("cannot deserialize raw TX - {e}")))
+        })
+    }
+
+    fn resolve_heights(&mut self, txids: &[Txid]) -> Vec<Result<WitnessAnchor, ResolverError>> {
+        // Transaction details are requested in one batch; the batch fails as a
+        // whole if any of the transactions is unknown to the server, in which
+        // case we fall back to per-transaction requests
+        let tip_height = match self.resolve_tip_height() {
+            Ok(height) => height,
+            Err(err) => return txids.iter().map(|_| Err(err.clone())).collect(),
+        };
+        let mut batch = Batch::default();
+        for txid in txids {
+            batch.raw(s!("blockchain.transaction.get"), vec![
+                Param::String(txid.to_string()),
+                Param::Bool(true),
+            ]);
+        }
+        let details = match self.batch_call(&batch) {
+            Ok(details) if details.len() == txids.len() => details,
+            _ => {
+                return txids
+                    .iter()
+                    .map(|txid| self.resolve_height(*txid))
+                    .collect();
+            }
+        };
+
+        // Mined transactions are located at the height derived from the
+        // number of confirmations with a second batch of merkle proof
+        // requests
+        let mined = txids
+            .iter()
+            .zip(&details)
+            .filter_map(|(txid, tx_details)| {
+                let confirmations = tx_details.get("confirmations")?.as_u64()?;
+                let block_time = tx_details.get("blocktime")?.as_i64()?;
+                let height = (tip_height + 1).checked_sub(u32::try_from(confirmations).ok()?)?;
+                Some((*txid, height, block_time))
+            })
+            .collect::<Vec<_>>();
+        let mut batch = Batch::default();
+        for (txid, height, _) in &mined {
+            batch.raw(s!("blockchain.transaction.get_merkle"), vec![
+                Param::String(txid.to_string()),
+                Param::Usize(*height as usize),
+            ]);
+        }
+        let proofs = match self.batch_call(&batch) {
+            Ok(proofs) if proofs.len() == mined.len() => proofs,
+            _ => vec![],
+        };
+        let located = mined
+            .iter()
+            .zip(&proofs)
+            .filter(|((_, height, _), proof)| {
+                proof.get("block_height").and_then(|h| h.as_u64()) == Some(*height as u64)
+            })
+            .filter_map(|((txid, height, time), _)| Some((*txid, WitnessPos::new(*height, *time)?)))
+            .collect::<HashMap<_, _>>();
+
+        txids
+            .iter()
+            .zip(&details)
+            .map(|(txid, tx_details)| {
+                let witness_id = XWitnessId::Bitcoin(*txid);
+                if let Some(pos) = located.get(txid) {
+                    return Ok(WitnessAnchor {
+                        witness_ord: WitnessOrd::OnChain(*pos),
+                        witness_id,
+                    });
+                }
+                if tx_details.get("confirmations").is_none() {
+                    return Ok(WitnessAnchor {
+                        witness_ord: WitnessOrd::OffChain,
+                        witness_id,
+                    });
+                }
+                // the server is lagging or has re-orged between the requests
+                self.resolve_height(*txid)
+            })
+            .collect()
+    }
+
+    fn resolve_pub_witnesses(&self, txids: &[Txid]) -> Vec<Result<Tx, Option<ResolverError>>> {
+        // The batch fails as a whole if any of the transactions is unknown to
+        // the server, in which case we fall back to per-transaction requests
+        match self.batch_transaction_get_raw(txids) {
+            Ok(raw_txes) if raw_txes.len() == txids.len() => raw_txes
+                .into_iter()
+                .map(|raw_tx| {
+                    Tx::consensus_deserialize(raw_tx).map_err(|e| {
+                        Some(ResolverError::permanent(format!("cannot deserialize raw TX - {e}")))
+                    })
+                })
+                .collect(),
+            _ => txids
+                .iter()
+                .map(|txid| self.resolve_pub_witness(*txid))
+                .collect(),
+        }
+    }
+
+    fn resolve_spv_proof(&self, txid: Txid, height: u32) -> Result<SpvProof, ResolverError> {
+        let res = check!(self.transaction_get_merkle(&txid, height as usize));
+        let header = check!(self.block_header(res.block_height));
+        Ok(SpvProof {
+            height: u32::try_from(res.block_height)
+                .map_err(|_| ResolverError::permanent("impossible height value"))?,
+            header,
+            pos: res.pos as u32,
+            merkle: res.merkle,
+        })
+    }
+
+    fn resolve_headers(&self, start: u32, count: u32) -> Result<Vec<BlockHeader>, ResolverError> {
+        Ok(check!(self.block_headers(start as usize, count as usize)).headers)
     }
 }
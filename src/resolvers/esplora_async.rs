@@ -0,0 +1,136 @@
+// RGB smart contracts for Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bp::Tx;
+use bpstd::Txid;
+use esplora::{AsyncClient, Error};
+use rgbstd::{WitnessAnchor, WitnessOrd, WitnessPos};
+
+use super::{AsyncRgbResolver, ChainParams, ResolverFuture, NETWORK_MISMATCH};
+use crate::XWitnessId;
+
+impl AsyncRgbResolver for AsyncClient {
+    fn check<'a>(&'a self, chain: &'a ChainParams) -> ResolverFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            // check the esplora server is for the correct network
+            let block_hash = self.block_hash(0).await?.to_string();
+            if chain.genesis_hash != block_hash {
+                return Err(NETWORK_MISMATCH.to_owned());
+            }
+            Ok(())
+        })
+    }
+
+    fn resolve_height(&self, txid: Txid) -> ResolverFuture<'_, Result<WitnessAnchor, String>> {
+        Box::pin(async move {
+            let status = self.tx_status(&txid).await?;
+            let ord = match status
+                .block_height
+                .and_then(|h| status.block_time.map(|t| (h, t)))
+            {
+                Some((h, t)) => WitnessOrd::OnChain(
+                    WitnessPos::new(h, t as i64).ok_or(Error::InvalidServerData)?,
+                ),
+                None => WitnessOrd::OffChain,
+            };
+            Ok(WitnessAnchor {
+                witness_ord: ord,
+                witness_id: XWitnessId::Bitcoin(txid),
+            })
+        })
+    }
+
+    fn resolve_pub_witness(&self, txid: Txid) -> ResolverFuture<'_, Result<Tx, Option<String>>> {
+        Box::pin(async move {
+            self.tx(&txid)
+                .await
+                .map_err(|e| match e {
+                    Error::TransactionNotFound(_) => None,
+                    e => Some(e.to_string()),
+                })?
+                .ok_or(None)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use amplify::hex::FromHex;
+    use esplora::Config;
+
+    use super::*;
+    use crate::resolvers::mock::{block_on, MockServer, GENESIS_HASH, GENESIS_TX, GENESIS_TXID};
+
+    fn esplora() -> (MockServer, AsyncClient) {
+        let server = MockServer::http(|_, path, _| match path {
+            "/block-height/0" => (200, GENESIS_HASH.as_bytes().to_vec()),
+            p if p == format!("/tx/{GENESIS_TXID}/raw") => {
+                (200, Vec::<u8>::from_hex(GENESIS_TX).unwrap())
+            }
+            p if p == format!("/tx/{GENESIS_TXID}/status") => (
+                200,
+                format!(
+                    r#"{{"confirmed":true,"block_height":1,"block_hash":"{GENESIS_HASH}","block_time":1231469665}}"#
+                )
+                .into_bytes(),
+            ),
+            p if p.ends_with("/status") => (200, br#"{"confirmed":false}"#.to_vec()),
+            _ => (404, b"Transaction not found".to_vec()),
+        });
+        let url = format!("http://{}", server.addr());
+        let client = AsyncClient::from_config(&url, Config::default()).unwrap();
+        (server, client)
+    }
+
+    #[test]
+    fn check_network() {
+        let (_server, client) = esplora();
+        block_on(client.check(&ChainParams::mainnet())).unwrap();
+        assert_eq!(
+            block_on(client.check(&ChainParams::regtest())),
+            Err(NETWORK_MISMATCH.to_owned())
+        );
+    }
+
+    #[test]
+    fn resolve_witness() {
+        let (_server, client) = esplora();
+        let txid = Txid::from_str(GENESIS_TXID).unwrap();
+        let tx = block_on(client.resolve_pub_witness(txid)).unwrap();
+        assert_eq!(tx.txid(), txid);
+        assert_eq!(block_on(client.resolve_pub_witness(Txid::coinbase())), Err(None));
+    }
+
+    #[test]
+    fn resolve_height() {
+        let (_server, client) = esplora();
+        let txid = Txid::from_str(GENESIS_TXID).unwrap();
+        let anchor = block_on(client.resolve_height(txid)).unwrap();
+        assert_eq!(
+            anchor.witness_ord,
+            WitnessOrd::OnChain(WitnessPos::new(1, 1231469665).unwrap())
+        );
+        let anchor = block_on(client.resolve_height(Txid::coinbase())).unwrap();
+        assert_eq!(anchor.witness_ord, WitnessOrd::OffChain);
+    }
+}
@@ -19,47 +19,164 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use bp::Tx;
-use bpstd::{Network, Txid};
-use esplora::{BlockingClient, Error};
+use std::str::FromStr;
+use std::thread;
+
+use amplify::ByteArray;
+use bp::{BlockHeader, Tx};
+use bpstd::Txid;
+use esplora::{BlockingClient, Error, MerkleProof};
 use rgbstd::{WitnessAnchor, WitnessOrd, WitnessPos};
 
-use super::RgbResolver;
+use super::{ChainParams, ResolverError, RgbResolver, SpvProof, TxStatus, NETWORK_MISMATCH};
 use crate::XWitnessId;
 
+impl From<Error> for ResolverError {
+    fn from(err: Error) -> Self {
+        match err {
+            // Transport failures, server errors and rate limiting
+            Error::Ureq(_) | Error::Io(_) => ResolverError::Backend(err.to_string()),
+            Error::HttpResponse(status) if status == 429 || status >= 500 => {
+                ResolverError::Backend(err.to_string())
+            }
+            err => ResolverError::permanent(err),
+        }
+    }
+}
+
+/// Number of requests run concurrently by batch methods.
+const CONCURRENCY: usize = 8;
+
 impl RgbResolver for BlockingClient {
-    fn check(&self, _network: Network, expected_block_hash: String) -> Result<(), String> {
+    fn check(&self, chain: &ChainParams) -> Result<(), ResolverError> {
         // check the esplora server is for the correct network
         let block_hash = self.block_hash(0)?.to_string();
-        if expected_block_hash != block_hash {
-            return Err(s!("resolver is for a network different from the wallet's one"));
+        if chain.genesis_hash != block_hash {
+            return Err(ResolverError::permanent(NETWORK_MISMATCH));
         }
         Ok(())
     }
 
-    fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, String> {
-        let status = self.tx_status(&txid)?;
-        let ord = match status
-            .block_height
-            .and_then(|h| status.block_time.map(|t| (h, t)))
-        {
-            Some((h, t)) => {
-                WitnessOrd::OnChain(WitnessPos::new(h, t as i64).ok_or(Error::InvalidServerData)?)
-            }
-            None => WitnessOrd::OffChain,
-        };
-        Ok(WitnessAnchor {
-            witness_ord: ord,
-            witness_id: XWitnessId::Bitcoin(txid),
-        })
+    fn resolve_tip_height(&self) -> Result<u32, ResolverError> { Ok(self.height()?) }
+
+    fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, ResolverError> {
+        tx_anchor(self, txid)
     }
 
-    fn resolve_pub_witness(&self, txid: Txid) -> Result<Tx, Option<String>> {
+    fn resolve_pub_witness(&self, txid: Txid) -> Result<Tx, Option<ResolverError>> {
         self.tx(&txid)
             .map_err(|e| match e {
                 Error::TransactionNotFound(_) => None,
-                e => Some(e.to_string()),
+                e => Some(e.into()),
             })?
             .ok_or(None)
     }
+
+    fn resolve_status(&mut self, txid: Txid) -> Result<TxStatus, ResolverError> {
+        let status = self.tx_status(&txid)?;
+        if let Some(height) = status.block_height.filter(|_| status.confirmed) {
+            let confirmations = self.height()?.saturating_sub(height) + 1;
+            return Ok(TxStatus::Mined {
+                height,
+                confirmations,
+            });
+        }
+        // Esplora reports unknown transactions as unconfirmed ones, thus we have
+        // to check whether the transaction is present in the mempool
+        match self.resolve_pub_witness(txid) {
+            Ok(_) => Ok(TxStatus::Mempool),
+            Err(None) => Ok(TxStatus::Unknown),
+            Err(Some(err)) => Err(err),
+        }
+    }
+
+    fn resolve_heights(&mut self, txids: &[Txid]) -> Vec<Result<WitnessAnchor, ResolverError>> {
+        let client = &*self;
+        concurrently(txids, |txid| tx_anchor(client, txid))
+    }
+
+    fn resolve_pub_witnesses(&self, txids: &[Txid]) -> Vec<Result<Tx, Option<ResolverError>>> {
+        concurrently(txids, |txid| self.resolve_pub_witness(txid))
+    }
+
+    fn resolve_spv_proof(&self, txid: Txid, _height: u32) -> Result<SpvProof, ResolverError> {
+        if !self.tx_status(&txid)?.confirmed {
+            return Err(ResolverError::permanent("transaction is not mined"));
+        }
+        // The client doesn't provide merkle proofs and block headers, so we
+        // request them from the REST API directly
+        let proof = self
+            .agent()
+            .get(&format!("{}/tx/{txid}/merkle-proof", self.url()))
+            .call()
+            .map_err(Error::from)?
+            .into_json::<MerkleProof>()
+            .map_err(|e| ResolverError::permanent(format!("invalid merkle proof - {e}")))?;
+        let header = block_header(self, proof.block_height)?;
+        Ok(SpvProof {
+            height: proof.block_height,
+            header,
+            pos: proof.pos as u32,
+            merkle: proof.merkle.iter().map(Txid::to_byte_array).collect(),
+        })
+    }
+
+    fn resolve_headers(&self, start: u32, count: u32) -> Result<Vec<BlockHeader>, ResolverError> {
+        let tip_height = self.height()?;
+        let heights = (start..start.saturating_add(count))
+            .take_while(|height| *height <= tip_height)
+            .collect::<Vec<_>>();
+        concurrently(&heights, |height| block_header(self, height))
+            .into_iter()
+            .collect()
+    }
+}
+
+fn block_header(client: &BlockingClient, height: u32) -> Result<BlockHeader, ResolverError> {
+    let block_hash = client.block_hash(height)?;
+    let hex = client
+        .agent()
+        .get(&format!("{}/block/{block_hash}/header", client.url()))
+        .call()
+        .map_err(Error::from)?
+        .into_string()
+        .map_err(Error::from)?;
+    BlockHeader::from_str(hex.trim())
+        .map_err(|e| ResolverError::permanent(format!("invalid block header - {e}")))
+}
+
+fn tx_anchor(client: &BlockingClient, txid: Txid) -> Result<WitnessAnchor, ResolverError> {
+    let status = client.tx_status(&txid)?;
+    let ord = match status
+        .block_height
+        .and_then(|h| status.block_time.map(|t| (h, t)))
+    {
+        Some((h, t)) => {
+            WitnessOrd::OnChain(WitnessPos::new(h, t as i64).ok_or(Error::InvalidServerData)?)
+        }
+        None => WitnessOrd::OffChain,
+    };
+    Ok(WitnessAnchor {
+        witness_ord: ord,
+        witness_id: XWitnessId::Bitcoin(txid),
+    })
+}
+
+fn concurrently<I: Copy + Send + Sync, T: Send>(items: &[I], f: impl Fn(I) -> T + Sync) -> Vec<T> {
+    let f = &f;
+    items
+        .chunks(CONCURRENCY)
+        .flat_map(|chunk| {
+            thread::scope(|scope| {
+                let handles = chunk
+                    .iter()
+                    .map(|item| scope.spawn(move || f(*item)))
+                    .collect::<Vec<_>>();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("resolver thread panicked"))
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect()
 }
@@ -0,0 +1,160 @@
+// RGB smart contracts for Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Local stand-ins for resolver backends, answering test requests with canned
+//! responses over HTTP or over the line-based JSON-RPC protocol of Electrum.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+/// Genesis block header of the bitcoin mainnet.
+#[cfg(feature = "electrum_async")]
+pub const GENESIS_HEADER: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+/// Coinbase transaction of the bitcoin mainnet genesis block.
+pub const GENESIS_TX: &str = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+pub const GENESIS_TXID: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+#[cfg(any(feature = "esplora_async", feature = "bitcoind_rpc"))]
+pub const GENESIS_HASH: &str = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+
+pub struct MockServer {
+    addr: SocketAddr,
+}
+
+impl MockServer {
+    fn spawn(serve: impl Fn(TcpStream) + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("unable to bind mock server");
+        let addr = listener.local_addr().expect("mock server address");
+        let serve = Arc::new(serve);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let serve = serve.clone();
+                thread::spawn(move || serve(stream));
+            }
+        });
+        MockServer { addr }
+    }
+
+    /// Serves HTTP requests, answering each of them with the status code and
+    /// the body returned by `handler` for the request method, path and body.
+    #[cfg(any(feature = "esplora_async", feature = "bitcoind_rpc"))]
+    pub fn http(
+        handler: impl Fn(&str, &str, &[u8]) -> (u16, Vec<u8>) + Send + Sync + 'static,
+    ) -> Self {
+        use std::io::Read;
+
+        Self::spawn(move |stream| {
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                let mut parts = line.split_whitespace();
+                let method = parts.next().unwrap_or_default().to_owned();
+                let path = parts.next().unwrap_or_default().to_owned();
+                let mut len = 0;
+                loop {
+                    let mut header = String::new();
+                    if reader.read_line(&mut header).unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let header = header.trim();
+                    if header.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            len = value.trim().parse().unwrap_or_default();
+                        }
+                    }
+                }
+                let mut body = vec![0u8; len];
+                if reader.read_exact(&mut body).is_err() {
+                    return;
+                }
+                let (code, resp) = handler(&method, &path, &body);
+                let head = format!("HTTP/1.1 {code} Mock\r\nContent-Length: {}\r\n\r\n", resp.len());
+                let stream = reader.get_mut();
+                if stream
+                    .write_all(head.as_bytes())
+                    .and_then(|_| stream.write_all(&resp))
+                    .is_err()
+                {
+                    return;
+                }
+                line.clear();
+            }
+        })
+    }
+
+    /// Serves Electrum JSON-RPC requests, including batches, answering each of
+    /// them with the result or the error message returned by `handler` for the
+    /// request method and parameters.
+    #[cfg(feature = "electrum_async")]
+    pub fn electrum(
+        handler: impl Fn(&str, &[serde_json::Value]) -> Result<serde_json::Value, String>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        use serde_json::{json, Value};
+
+        Self::spawn(move |stream| {
+            let respond = |req: &Value| {
+                let method = req["method"].as_str().unwrap_or_default();
+                let params = req["params"].as_array().cloned().unwrap_or_default();
+                match handler(method, &params) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": req["id"], "result": result }),
+                    Err(message) => json!({
+                        "jsonrpc": "2.0",
+                        "id": req["id"],
+                        "error": { "code": 1, "message": message }
+                    }),
+                }
+            };
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                let resp = match serde_json::from_str(&line).expect("invalid JSON-RPC request") {
+                    Value::Array(batch) => Value::Array(batch.iter().map(respond).collect()),
+                    req => respond(&req),
+                };
+                let mut data = serde_json::to_vec(&resp).expect("JSON serialization");
+                data.push(b'\n');
+                if reader.get_mut().write_all(&data).is_err() {
+                    return;
+                }
+                line.clear();
+            }
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr { self.addr }
+}
+
+/// Runs the future to completion on a single-threaded runtime.
+#[cfg(any(feature = "esplora_async", feature = "electrum_async"))]
+pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("tokio runtime")
+        .block_on(future)
+}
@@ -20,9 +20,45 @@
 // limitations under the License.
 
 mod any;
+#[cfg(any(feature = "esplora_async", feature = "electrum_async"))]
+mod any_async;
+mod cache;
+mod chain;
+#[cfg(all(
+    test,
+    any(feature = "esplora_async", feature = "electrum_async", feature = "bitcoind_rpc")
+))]
+mod mock;
+mod observer;
+mod offline;
+mod quorum;
+mod retry;
+mod spv;
+mod witnesses;
 #[cfg(feature = "esplora_blocking")]
 pub mod esplora_blocking;
 #[cfg(feature = "electrum_blocking")]
 pub mod electrum_blocking;
+#[cfg(feature = "bitcoind_rpc")]
+pub mod bitcoind_rpc;
+#[cfg(feature = "esplora_async")]
+pub mod esplora_async;
+#[cfg(feature = "electrum_async")]
+pub mod electrum_async;
 
-pub use any::{AnyResolver, RgbResolver};
+pub use any::{AnyResolver, ResolverError, RgbResolver, TxStatus};
+pub(crate) use any::NETWORK_MISMATCH;
+#[cfg(any(feature = "esplora_async", feature = "electrum_async"))]
+pub use any_async::{AnyAsyncResolver, AsyncRgbResolver, ResolverFuture};
+pub use cache::{CachePolicy, CachingResolver};
+pub use chain::{ChainParams, Checkpoint, PowParams};
+#[cfg(feature = "log")]
+pub use observer::LogObserver;
+pub use observer::{CallOutcome, ResolverCall, ResolverCounters, ResolverEvent, ResolverObserver};
+use observer::{ObservedResolver, SharedObserver};
+pub use offline::OfflineResolver;
+pub use quorum::{QuorumError, QuorumResolver};
+pub use retry::{RetryPolicy, RetryingResolver};
+pub use spv::{SpvError, SpvProof, SpvResolver};
+pub(crate) use witnesses::pos_timestamp;
+pub use witnesses::{WitnessBundle, WitnessBundleError};
@@ -0,0 +1,337 @@
+// RGB smart contracts for Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use bp::{BlockHeader, Tx};
+use bpstd::Txid;
+use rgbstd::WitnessAnchor;
+
+use super::{ChainParams, ResolverError, RgbResolver, SpvProof, TxStatus};
+
+/// Resolver request reported to [`ResolverObserver`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
+#[display(lowercase)]
+pub enum ResolverCall {
+    Check,
+    #[display("tip-height")]
+    TipHeight,
+    Height,
+    Status,
+    #[display("pub-witness")]
+    PubWitness,
+    #[display("spv-proof")]
+    SpvProof,
+}
+
+/// Outcome of a resolver request reported to [`ResolverObserver`].
+#[derive(Clone, Eq, PartialEq, Debug, Display)]
+pub enum CallOutcome {
+    #[display("success")]
+    Success,
+
+    /// The transaction is unknown to the backend.
+    #[display("not found")]
+    NotFound,
+
+    #[display("error: {0}")]
+    Error(String),
+}
+
+impl CallOutcome {
+    pub fn with<T, E: ToString>(res: &Result<T, E>) -> Self {
+        match res {
+            Ok(_) => CallOutcome::Success,
+            Err(err) => CallOutcome::Error(err.to_string()),
+        }
+    }
+
+    /// Constructs outcome from the result of witness transaction resolution,
+    /// where `None` error means unknown transaction.
+    pub fn with_unknown<T, E: ToString>(res: &Result<T, Option<E>>) -> Self {
+        match res {
+            Ok(_) => CallOutcome::Success,
+            Err(None) => CallOutcome::NotFound,
+            Err(Some(err)) => CallOutcome::Error(err.to_string()),
+        }
+    }
+}
+
+/// Information about a single resolver request. For the requests made in
+/// batches, the latency of the whole batch is divided equally among them.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ResolverEvent<'a> {
+    pub call: ResolverCall,
+    pub txid: Option<Txid>,
+    /// Name of the backend; requests made to the members of a quorum are
+    /// reported under the member names.
+    pub backend: &'a str,
+    pub latency: Duration,
+    pub outcome: CallOutcome,
+}
+
+/// Instrumentation hook for [`crate::AnyResolver`].
+///
+/// Requests answered without querying the backend (for terminal transactions
+/// of the consignment, witnesses pre-resolved with
+/// [`crate::AnyResolver::resolve_consignment`] or the ones kept by
+/// [`crate::CachingResolver`]) are reported with separate methods and do not
+/// produce [`ResolverEvent`]s.
+pub trait ResolverObserver {
+    fn on_call(&self, event: &ResolverEvent);
+    fn on_terminal_hit(&self, _call: ResolverCall, _txid: Txid) {}
+    fn on_cache_hit(&self, _call: ResolverCall, _txid: Txid) {}
+}
+
+/// Observer of [`crate::AnyResolver`], shared with the members of its quorum,
+/// which can be set after the resolver is constructed.
+pub(super) type SharedObserver = Rc<RefCell<Option<Box<dyn ResolverObserver>>>>;
+
+/// Member of a quorum reporting its own requests to the observer under the
+/// member name, such that slow or failing backends can be told apart.
+///
+/// Requests for block headers are not reported.
+pub(super) struct ObservedResolver {
+    pub name: String,
+    pub inner: Box<dyn RgbResolver>,
+    pub observer: SharedObserver,
+}
+
+impl ObservedResolver {
+    fn report(
+        &self,
+        call: ResolverCall,
+        txid: Option<Txid>,
+        latency: Duration,
+        outcome: CallOutcome,
+    ) {
+        if let Some(observer) = &*self.observer.borrow() {
+            observer.on_call(&ResolverEvent {
+                call,
+                txid,
+                backend: &self.name,
+                latency,
+                outcome,
+            });
+        }
+    }
+}
+
+impl RgbResolver for ObservedResolver {
+    fn check(&self, chain: &ChainParams) -> Result<(), ResolverError> {
+        let started = Instant::now();
+        let res = self.inner.check(chain);
+        self.report(ResolverCall::Check, None, started.elapsed(), CallOutcome::with(&res));
+        res
+    }
+
+    fn resolve_tip_height(&self) -> Result<u32, ResolverError> {
+        let started = Instant::now();
+        let res = self.inner.resolve_tip_height();
+        self.report(ResolverCall::TipHeight, None, started.elapsed(), CallOutcome::with(&res));
+        res
+    }
+
+    fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, ResolverError> {
+        let started = Instant::now();
+        let res = self.inner.resolve_height(txid);
+        self.report(ResolverCall::Height, Some(txid), started.elapsed(), CallOutcome::with(&res));
+        res
+    }
+
+    fn resolve_pub_witness(&self, txid: Txid) -> Result<Tx, Option<ResolverError>> {
+        let started = Instant::now();
+        let res = self.inner.resolve_pub_witness(txid);
+        let outcome = CallOutcome::with_unknown(&res);
+        self.report(ResolverCall::PubWitness, Some(txid), started.elapsed(), outcome);
+        res
+    }
+
+    fn resolve_status(&mut self, txid: Txid) -> Result<TxStatus, ResolverError> {
+        let started = Instant::now();
+        let res = self.inner.resolve_status(txid);
+        self.report(ResolverCall::Status, Some(txid), started.elapsed(), CallOutcome::with(&res));
+        res
+    }
+
+    fn resolve_heights(&mut self, txids: &[Txid]) -> Vec<Result<WitnessAnchor, ResolverError>> {
+        let started = Instant::now();
+        let resolved = self.inner.resolve_heights(txids);
+        let latency = started.elapsed() / txids.len().max(1) as u32;
+        for (txid, res) in txids.iter().zip(&resolved) {
+            self.report(ResolverCall::Height, Some(*txid), latency, CallOutcome::with(res));
+        }
+        resolved
+    }
+
+    fn resolve_pub_witnesses(&self, txids: &[Txid]) -> Vec<Result<Tx, Option<ResolverError>>> {
+        let started = Instant::now();
+        let resolved = self.inner.resolve_pub_witnesses(txids);
+        let latency = started.elapsed() / txids.len().max(1) as u32;
+        for (txid, res) in txids.iter().zip(&resolved) {
+            let outcome = CallOutcome::with_unknown(res);
+            self.report(ResolverCall::PubWitness, Some(*txid), latency, outcome);
+        }
+        resolved
+    }
+
+    fn resolve_spv_proof(&self, txid: Txid, height: u32) -> Result<SpvProof, ResolverError> {
+        let started = Instant::now();
+        let res = self.inner.resolve_spv_proof(txid, height);
+        let outcome = CallOutcome::with(&res);
+        self.report(ResolverCall::SpvProof, Some(txid), started.elapsed(), outcome);
+        res
+    }
+
+    fn resolve_headers(&self, start: u32, count: u32) -> Result<Vec<BlockHeader>, ResolverError> {
+        self.inner.resolve_headers(start, count)
+    }
+
+    fn is_cached(&self, call: ResolverCall, txid: Txid) -> bool { self.inner.is_cached(call, txid) }
+}
+
+/// Counters of the requests made through [`crate::AnyResolver`].
+#[derive(Debug, Default)]
+pub struct ResolverCounters {
+    calls: AtomicU64,
+    failures: AtomicU64,
+    not_found: AtomicU64,
+    terminal_hits: AtomicU64,
+    cache_hits: AtomicU64,
+    latency_us: AtomicU64,
+}
+
+impl ResolverCounters {
+    /// Number of requests made to the backend.
+    pub fn calls(&self) -> u64 { self.calls.load(Ordering::Relaxed) }
+    /// Number of backend requests failed with an error.
+    pub fn failures(&self) -> u64 { self.failures.load(Ordering::Relaxed) }
+    /// Number of backend requests for transactions unknown to the backend.
+    pub fn not_found(&self) -> u64 { self.not_found.load(Ordering::Relaxed) }
+    /// Number of requests answered from the consignment terminal transactions.
+    pub fn terminal_hits(&self) -> u64 { self.terminal_hits.load(Ordering::Relaxed) }
+    /// Number of requests answered from pre-resolved witnesses or the
+    /// resolver cache.
+    pub fn cache_hits(&self) -> u64 { self.cache_hits.load(Ordering::Relaxed) }
+    /// Total time spent in the backend requests.
+    pub fn latency(&self) -> Duration {
+        Duration::from_micros(self.latency_us.load(Ordering::Relaxed))
+    }
+}
+
+impl ResolverObserver for ResolverCounters {
+    fn on_call(&self, event: &ResolverEvent) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.latency_us
+            .fetch_add(event.latency.as_micros() as u64, Ordering::Relaxed);
+        match event.outcome {
+            CallOutcome::Success => {}
+            CallOutcome::NotFound => {
+                self.not_found.fetch_add(1, Ordering::Relaxed);
+            }
+            CallOutcome::Error(_) => {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn on_terminal_hit(&self, _call: ResolverCall, _txid: Txid) {
+        self.terminal_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_cache_hit(&self, _call: ResolverCall, _txid: Txid) {
+        self.cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Observer emitting `log` records for each resolver request: failed requests
+/// are logged with warning level, others with debug level.
+#[cfg(feature = "log")]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct LogObserver;
+
+#[cfg(feature = "log")]
+impl ResolverObserver for LogObserver {
+    fn on_call(&self, event: &ResolverEvent) {
+        let txid = event
+            .txid
+            .map(|txid| format!(" for {txid}"))
+            .unwrap_or_default();
+        let level = match event.outcome {
+            CallOutcome::Error(_) => log::Level::Warn,
+            _ => log::Level::Debug,
+        };
+        log::log!(
+            level,
+            "resolver {} {}{txid} took {:?}: {}",
+            event.backend,
+            event.call,
+            event.latency,
+            event.outcome
+        );
+    }
+
+    fn on_terminal_hit(&self, call: ResolverCall, txid: Txid) {
+        log::trace!("resolver {call} for {txid} is answered from terminal transactions");
+    }
+
+    fn on_cache_hit(&self, call: ResolverCall, txid: Txid) {
+        log::trace!("resolver {call} for {txid} is answered from cache");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counters() {
+        let counters = ResolverCounters::default();
+        let txid = Txid::coinbase();
+        for (outcome, ms) in [
+            (CallOutcome::Success, 10),
+            (CallOutcome::NotFound, 20),
+            (CallOutcome::Error(s!("timeout")), 30),
+            (CallOutcome::Error(s!("timeout")), 40),
+        ] {
+            counters.on_call(&ResolverEvent {
+                call: ResolverCall::PubWitness,
+                txid: Some(txid),
+                backend: "esplora",
+                latency: Duration::from_millis(ms),
+                outcome,
+            });
+        }
+        counters.on_terminal_hit(ResolverCall::PubWitness, txid);
+        counters.on_cache_hit(ResolverCall::Height, txid);
+        counters.on_cache_hit(ResolverCall::PubWitness, txid);
+
+        assert_eq!(counters.calls(), 4);
+        assert_eq!(counters.not_found(), 1);
+        assert_eq!(counters.failures(), 2);
+        assert_eq!(counters.terminal_hits(), 1);
+        assert_eq!(counters.cache_hits(), 2);
+        assert_eq!(counters.latency(), Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,261 @@
+// RGB smart contracts for Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io;
+use std::path::Path;
+
+use bp::{BlockHeader, Tx};
+use bpstd::Txid;
+use rgbstd::{WitnessAnchor, WitnessOrd, XWitnessId};
+
+use super::{ChainParams, ResolverError, RgbResolver, SpvProof, TxStatus, WitnessBundle};
+
+/// Resolver for air-gapped environments, using witness data exported from an
+/// online machine into a [`WitnessBundle`] file.
+///
+/// Witness transactions which are not present in the bundle are reported as
+/// unknown; witnesses without mining information are reported as off-chain.
+/// Since the bundle doesn't tell whether such witnesses are still in the
+/// mempool, status of unmined transactions can't be resolved.
+///
+/// Mining information is checked against the SPV proof of the transaction, if
+/// the bundle contains one. The header chain exported into the bundle is
+/// served to [`SpvResolver`](super::SpvResolver), which validates it and the
+/// proofs against the chain checkpoint.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct OfflineResolver {
+    bundle: WitnessBundle,
+}
+
+impl From<WitnessBundle> for OfflineResolver {
+    fn from(bundle: WitnessBundle) -> Self { OfflineResolver { bundle } }
+}
+
+impl OfflineResolver {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        WitnessBundle::load(path).map(OfflineResolver::from)
+    }
+
+    pub fn bundle(&self) -> &WitnessBundle { &self.bundle }
+}
+
+impl RgbResolver for OfflineResolver {
+    fn check(&self, chain: &ChainParams) -> Result<(), ResolverError> {
+        match &self.bundle.genesis_hash {
+            Some(hash) if *hash == chain.genesis_hash => Ok(()),
+            Some(_) => Err(ResolverError::permanent(
+                "witness bundle is for a network different from the wallet's one",
+            )),
+            None => Err(ResolverError::permanent("witness bundle doesn't specify its network")),
+        }
+    }
+
+    fn resolve_tip_height(&self) -> Result<u32, ResolverError> {
+        self.bundle
+            .tip_height
+            .ok_or_else(|| {
+                ResolverError::permanent("witness bundle doesn't specify blockchain tip height")
+            })
+    }
+
+    fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, ResolverError> {
+        let Some(anchor) = self.bundle.anchors.get(&txid) else {
+            return Ok(WitnessAnchor {
+                witness_ord: WitnessOrd::OffChain,
+                witness_id: XWitnessId::Bitcoin(txid),
+            });
+        };
+        if let (WitnessOrd::OnChain(pos), Some(proof)) =
+            (anchor.witness_ord, self.bundle.proofs.get(&txid))
+        {
+            proof.verify_anchor(txid, pos)?;
+        }
+        Ok(*anchor)
+    }
+
+    fn resolve_pub_witness(&self, txid: Txid) -> Result<Tx, Option<ResolverError>> {
+        self.bundle.txes.get(&txid).cloned().ok_or(None)
+    }
+
+    fn resolve_status(&mut self, txid: Txid) -> Result<TxStatus, ResolverError> {
+        let WitnessOrd::OnChain(pos) = self.resolve_height(txid)?.witness_ord else {
+            return Err(ResolverError::permanent(format!(
+                "witness bundle doesn't provide mempool status of {txid}"
+            )));
+        };
+        let height = u32::from(pos.height());
+        let confirmations = self.resolve_tip_height()?.saturating_sub(height) + 1;
+        Ok(TxStatus::Mined {
+            height,
+            confirmations,
+        })
+    }
+
+    fn resolve_spv_proof(&self, txid: Txid, _height: u32) -> Result<SpvProof, ResolverError> {
+        self.bundle
+            .proofs
+            .get(&txid)
+            .cloned()
+            .ok_or_else(|| {
+                ResolverError::permanent(format!(
+                    "witness bundle doesn't contain SPV proof for {txid}"
+                ))
+            })
+    }
+
+    fn resolve_headers(&self, start: u32, count: u32) -> Result<Vec<BlockHeader>, ResolverError> {
+        Ok((start..start.saturating_add(count))
+            .map_while(|height| self.bundle.headers.get(&height).copied())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, mem, process};
+
+    use amplify::hex::FromHex;
+    use amplify::ByteArray;
+    use bp::ConsensusDecode;
+
+    use super::*;
+    use crate::resolvers::{AnyResolver, PowParams, SpvError, SpvResolver};
+    use crate::WitnessPos;
+
+    /// Transaction spending a null outpoint to no outputs.
+    const TX: &str = "0100000001000000000000000000000000000000000000000000000000000000000000\
+                      0000ffffffff00ffffffff0000000000";
+
+    /// Bundle with a witness mined in the block following a regtest-like
+    /// genesis block.
+    fn fixture() -> (WitnessBundle, ChainParams, Txid) {
+        let tx = Tx::consensus_deserialize(Vec::<u8>::from_hex(TX).unwrap()).unwrap();
+        let txid = tx.txid();
+        let genesis = BlockHeader {
+            version: 1,
+            prev_block_hash: [0u8; 32].into(),
+            merkle_root: [0x11; 32].into(),
+            time: 1296688602,
+            bits: PowParams::regtest().limit,
+            nonce: 0,
+        };
+        let mut proof = SpvProof {
+            height: 1,
+            header: BlockHeader {
+                prev_block_hash: genesis.block_hash(),
+                merkle_root: txid.to_byte_array().into(),
+                time: genesis.time + 600,
+                ..genesis
+            },
+            pos: 0,
+            merkle: vec![],
+        };
+        while proof.verify(txid).is_err() {
+            proof.header.nonce += 1;
+        }
+        let chain = ChainParams {
+            genesis_hash: genesis.block_hash().to_string(),
+            ..ChainParams::regtest()
+        };
+        let mut bundle = WitnessBundle {
+            genesis_hash: Some(chain.genesis_hash.clone()),
+            tip_height: Some(1),
+            ..default!()
+        };
+        bundle.add_tx(tx);
+        bundle.add_anchor(WitnessAnchor {
+            witness_ord: WitnessOrd::OnChain(
+                WitnessPos::new(1, proof.header.time as i64).unwrap(),
+            ),
+            witness_id: XWitnessId::Bitcoin(txid),
+        });
+        bundle.headers.insert(0, genesis);
+        bundle.headers.insert(1, proof.header);
+        bundle.proofs.insert(txid, proof);
+        (bundle, chain, txid)
+    }
+
+    #[test]
+    fn resolve() {
+        let (bundle, chain, txid) = fixture();
+        let mut resolver = OfflineResolver::from(bundle.clone());
+        resolver.check(&chain).unwrap();
+        assert!(resolver.check(&ChainParams::regtest()).is_err());
+        assert_eq!(resolver.resolve_tip_height(), Ok(1));
+        assert_eq!(resolver.resolve_height(txid).as_ref(), Ok(&bundle.anchors[&txid]));
+        assert_eq!(resolver.resolve_pub_witness(txid).as_ref(), Ok(&bundle.txes[&txid]));
+        assert_eq!(resolver.resolve_status(txid), Ok(TxStatus::Mined {
+            height: 1,
+            confirmations: 1
+        }));
+        assert_eq!(resolver.resolve_spv_proof(txid, 1).as_ref(), Ok(&bundle.proofs[&txid]));
+        assert_eq!(resolver.resolve_headers(1, 10), Ok(vec![bundle.headers[&1]]));
+
+        let unknown = Txid::coinbase();
+        assert_eq!(resolver.resolve_pub_witness(unknown), Err(None));
+        assert_eq!(resolver.resolve_height(unknown).unwrap().witness_ord, WitnessOrd::OffChain);
+        assert!(resolver.resolve_status(unknown).is_err());
+    }
+
+    #[test]
+    fn invalid_proof() {
+        let (mut bundle, _, txid) = fixture();
+        bundle.proofs.get_mut(&txid).unwrap().merkle = vec![[0x33; 32]];
+        let mut resolver = OfflineResolver::from(bundle);
+        let err = resolver.resolve_height(txid).unwrap_err();
+        assert!(!err.is_transient());
+        assert!(resolver.resolve_status(txid).is_err());
+
+        let (mut bundle, _, txid) = fixture();
+        bundle.anchors.get_mut(&txid).unwrap().witness_ord =
+            WitnessOrd::OnChain(WitnessPos::new(2, 1296689202).unwrap());
+        let mut resolver = OfflineResolver::from(bundle);
+        assert_eq!(
+            resolver.resolve_height(txid),
+            Err(SpvError::AnchorMismatch(txid).into())
+        );
+    }
+
+    #[test]
+    fn spv() {
+        let (bundle, chain, txid) = fixture();
+        let anchor = bundle.anchors[&txid];
+        let mut resolver = SpvResolver::new(OfflineResolver::from(bundle.clone()), &chain).unwrap();
+        assert_eq!(resolver.resolve_height(txid), Ok(anchor));
+
+        let (mut bundle, chain, txid) = fixture();
+        bundle.headers.remove(&0);
+        let mut resolver = SpvResolver::new(OfflineResolver::from(bundle), &chain).unwrap();
+        assert_eq!(resolver.resolve_height(txid), Err(SpvError::MissingHeader(0).into()));
+    }
+    #[test]
+    fn export_headers() {
+        let (mut bundle, chain, _) = fixture();
+        let path = env::temp_dir().join(format!("rgb-offline-headers-{}", process::id()));
+        bundle.save(&path).unwrap();
+        let mut resolver = AnyResolver::offline(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let headers = mem::take(&mut bundle.headers);
+        resolver.export_headers(&chain, &mut bundle).unwrap();
+        assert_eq!(bundle.headers, headers);
+    }
+}
@@ -0,0 +1,304 @@
+// RGB smart contracts for Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::{self, Display, Formatter};
+
+use bp::{BlockHeader, Tx};
+use bpstd::Txid;
+use rgbstd::WitnessAnchor;
+
+use super::{ChainParams, ResolverError, RgbResolver, SpvProof};
+
+/// Error indicating that resolver backends were unable to reach the required
+/// level of agreement.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct QuorumError {
+    /// Transaction which was resolved, if any.
+    pub txid: Option<Txid>,
+    /// Number of backends required to agree.
+    pub threshold: usize,
+    /// Responses of the backends, grouped by the response. Each group lists
+    /// names of the backends which provided it.
+    pub responses: Vec<(String, Vec<String>)>,
+}
+
+impl Display for QuorumError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let total = self
+            .responses
+            .iter()
+            .map(|(_, names)| names.len())
+            .sum::<usize>();
+        write!(f, "resolvers haven't reached {}-of-{total} agreement", self.threshold)?;
+        if let Some(txid) = self.txid {
+            write!(f, " on {txid}")?;
+        }
+        f.write_str(":")?;
+        for (response, names) in &self.responses {
+            write!(f, "\n- {}: {response}", names.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for QuorumError {}
+
+/// Resolver querying multiple backends and requiring M-of-N of them to agree
+/// on the returned data.
+///
+/// With M not exceeding a half of N several different responses may be
+/// provided by M backends each; such responses are reported as a failure to
+/// reach the agreement.
+pub struct QuorumResolver {
+    backends: Vec<(String, Box<dyn RgbResolver>)>,
+    threshold: usize,
+}
+
+impl QuorumResolver {
+    /// Constructs quorum resolver from a list of named backends, requiring
+    /// `threshold` of them to agree.
+    pub fn new(
+        threshold: usize,
+        backends: impl IntoIterator<Item = (String, Box<dyn RgbResolver>)>,
+    ) -> Result<Self, String> {
+        let backends = backends.into_iter().collect::<Vec<_>>();
+        if threshold == 0 || threshold > backends.len() {
+            return Err(format!(
+                "invalid quorum threshold {threshold} for {} resolver(s)",
+                backends.len()
+            ));
+        }
+        Ok(QuorumResolver {
+            backends,
+            threshold,
+        })
+    }
+
+    pub fn threshold(&self) -> usize { self.threshold }
+
+    pub fn backend_names(&self) -> impl Iterator<Item = &str> {
+        self.backends.iter().map(|(name, _)| name.as_str())
+    }
+
+    fn quorum<T: Clone + Eq>(
+        &self,
+        txid: Option<Txid>,
+        responses: impl IntoIterator<Item = (String, Result<T, ResolverError>)>,
+        describe: impl Fn(&T) -> String,
+    ) -> Result<T, QuorumError> {
+        let mut groups = Vec::<(Result<T, ResolverError>, Vec<String>)>::new();
+        for (name, response) in responses {
+            match groups.iter_mut().find(|(r, _)| *r == response) {
+                Some((_, names)) => names.push(name),
+                None => groups.push((response, vec![name])),
+            }
+        }
+        // Competing responses reaching the threshold mean there is no agreement
+        let mut agreed = groups
+            .iter()
+            .filter(|(r, names)| r.is_ok() && names.len() >= self.threshold);
+        if let (Some((Ok(value), _)), None) = (agreed.next(), agreed.next()) {
+            return Ok(value.clone());
+        }
+        Err(QuorumError {
+            txid,
+            threshold: self.threshold,
+            responses: groups
+                .into_iter()
+                .map(|(response, names)| {
+                    let response = match response {
+                        Ok(value) => describe(&value),
+                        Err(err) => format!("error: {err}"),
+                    };
+                    (response, names)
+                })
+                .collect(),
+        })
+    }
+
+    pub fn check_quorum(&self, chain: &ChainParams) -> Result<(), QuorumError> {
+        let responses = self
+            .backends
+            .iter()
+            .map(|(name, backend)| (name.clone(), backend.check(chain)));
+        self.quorum(None, responses, |_| s!("ok"))
+    }
+
+    /// Returns the highest height which is reached by at least `threshold`
+    /// backends.
+    pub fn resolve_tip_height_quorum(&self) -> Result<u32, QuorumError> {
+        let mut heights = vec![];
+        let mut errors = vec![];
+        for (name, backend) in &self.backends {
+            match backend.resolve_tip_height() {
+                Ok(height) => heights.push(height),
+                Err(err) => errors.push((format!("error: {err}"), vec![name.clone()])),
+            }
+        }
+        heights.sort_unstable_by(|a, b| b.cmp(a));
+        heights.get(self.threshold - 1).copied().ok_or(QuorumError {
+            txid: None,
+            threshold: self.threshold,
+            responses: errors,
+        })
+    }
+
+    pub fn resolve_height_quorum(&mut self, txid: Txid) -> Result<WitnessAnchor, QuorumError> {
+        let responses = self
+            .backends
+            .iter_mut()
+            .map(|(name, backend)| (name.clone(), backend.resolve_height(txid)))
+            .collect::<Vec<_>>();
+        self.quorum(Some(txid), responses, |anchor| format!("{:?}", anchor.witness_ord))
+    }
+
+    pub fn resolve_pub_witness_quorum(&self, txid: Txid) -> Result<Option<Tx>, QuorumError> {
+        let responses = self.backends.iter().map(|(name, backend)| {
+            let response = match backend.resolve_pub_witness(txid) {
+                Ok(tx) => Ok(Some(tx)),
+                Err(None) => Ok(None),
+                Err(Some(err)) => Err(err),
+            };
+            (name.clone(), response)
+        });
+        self.quorum(Some(txid), responses, |tx| match tx {
+            Some(tx) => format!("transaction {}", tx.txid()),
+            None => s!("transaction not found"),
+        })
+    }
+
+    pub fn resolve_spv_proof_quorum(
+        &self,
+        txid: Txid,
+        height: u32,
+    ) -> Result<SpvProof, QuorumError> {
+        let responses = self
+            .backends
+            .iter()
+            .map(|(name, backend)| (name.clone(), backend.resolve_spv_proof(txid, height)));
+        self.quorum(Some(txid), responses, |proof| {
+            format!("block {} position {}", proof.header.block_hash(), proof.pos)
+        })
+    }
+
+    pub fn resolve_headers_quorum(
+        &self,
+        start: u32,
+        count: u32,
+    ) -> Result<Vec<BlockHeader>, QuorumError> {
+        let responses = self
+            .backends
+            .iter()
+            .map(|(name, backend)| (name.clone(), backend.resolve_headers(start, count)));
+        self.quorum(None, responses, |headers| match headers.last() {
+            Some(header) => {
+                format!("{} headers up to block {}", headers.len(), header.block_hash())
+            }
+            None => s!("no headers"),
+        })
+    }
+}
+
+impl RgbResolver for QuorumResolver {
+    fn check(&self, chain: &ChainParams) -> Result<(), ResolverError> {
+        self.check_quorum(chain).map_err(ResolverError::from)
+    }
+
+    fn resolve_tip_height(&self) -> Result<u32, ResolverError> {
+        self.resolve_tip_height_quorum().map_err(ResolverError::from)
+    }
+
+    fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, ResolverError> {
+        self.resolve_height_quorum(txid).map_err(ResolverError::from)
+    }
+
+    fn resolve_pub_witness(&self, txid: Txid) -> Result<Tx, Option<ResolverError>> {
+        self.resolve_pub_witness_quorum(txid)
+            .map_err(|e| Some(e.into()))?
+            .ok_or(None)
+    }
+
+    fn resolve_spv_proof(&self, txid: Txid, height: u32) -> Result<SpvProof, ResolverError> {
+        self.resolve_spv_proof_quorum(txid, height)
+            .map_err(ResolverError::from)
+    }
+
+    fn resolve_headers(&self, start: u32, count: u32) -> Result<Vec<BlockHeader>, ResolverError> {
+        self.resolve_headers_quorum(start, count)
+            .map_err(ResolverError::from)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rgbstd::{WitnessOrd, WitnessPos, XWitnessId};
+
+    use super::*;
+
+    /// Backend reporting all transactions as mined at the same height.
+    struct Mined(u32);
+
+    impl RgbResolver for Mined {
+        fn check(&self, _chain: &ChainParams) -> Result<(), ResolverError> { Ok(()) }
+        fn resolve_tip_height(&self) -> Result<u32, ResolverError> { Ok(self.0) }
+        fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, ResolverError> {
+            let pos = WitnessPos::new(self.0, 1231469665).unwrap();
+            Ok(WitnessAnchor {
+                witness_ord: WitnessOrd::OnChain(pos),
+                witness_id: XWitnessId::Bitcoin(txid),
+            })
+        }
+        fn resolve_pub_witness(&self, _txid: Txid) -> Result<Tx, Option<ResolverError>> {
+            Err(None)
+        }
+    }
+
+    fn quorum(threshold: usize, heights: &[u32]) -> QuorumResolver {
+        let backends = heights
+            .iter()
+            .enumerate()
+            .map(|(no, height)| {
+                (format!("#{no}"), Box::new(Mined(*height)) as Box<dyn RgbResolver>)
+            });
+        QuorumResolver::new(threshold, backends).unwrap()
+    }
+
+    #[test]
+    fn agreement() {
+        let mut resolver = quorum(2, &[100, 100, 101]);
+        let anchor = resolver.resolve_height(Txid::coinbase()).unwrap();
+        assert_eq!(
+            anchor.witness_ord,
+            WitnessOrd::OnChain(WitnessPos::new(100, 1231469665).unwrap())
+        );
+    }
+
+    #[test]
+    fn competing_groups() {
+        let mut resolver = quorum(2, &[100, 100, 101, 101]);
+        let Err(ResolverError::Quorum(err)) = resolver.resolve_height(Txid::coinbase()) else {
+            panic!("competing responses must not reach the quorum");
+        };
+        assert_eq!(err.txid, Some(Txid::coinbase()));
+        assert_eq!(err.threshold, 2);
+        assert_eq!(err.responses.len(), 2);
+    }
+}
@@ -0,0 +1,240 @@
+// RGB smart contracts for Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::thread;
+use std::time::Duration;
+
+use bp::{BlockHeader, Tx};
+use bpstd::Txid;
+use rgbstd::WitnessAnchor;
+
+use super::{ChainParams, ResolverCall, ResolverError, RgbResolver, SpvProof, TxStatus};
+
+/// Policy for retrying failed resolver requests.
+///
+/// Only network and server errors are retried; transactions which are reported
+/// as unknown by the resolver, as well as definitive answers like a network
+/// mismatch, are returned as such without retries.
+///
+/// Timeout of a single request is applied by the backend itself and must be
+/// provided with the backend configuration when the resolver is constructed.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct RetryPolicy {
+    /// Maximal number of attempts for each request, including the first one.
+    pub max_attempts: u8,
+    /// Delay before the first retry; doubled with each next attempt.
+    pub backoff: Duration,
+    /// Maximal delay between two attempts.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Policy which doesn't retry failed requests.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..default!()
+        }
+    }
+
+    /// Returns delay before the given attempt (counting from zero for the first
+    /// attempt, which is not delayed).
+    pub fn delay(&self, attempt: u8) -> Duration {
+        if attempt == 0 {
+            return Duration::ZERO;
+        }
+        self.backoff
+            .checked_mul(1 << (attempt - 1).min(31))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+
+    /// Runs `f` until it succeeds, returns an error which must not be retried
+    /// (for which `retry` returns `false`), or the number of attempts is
+    /// exhausted. In the later case the last error is returned.
+    pub fn run<T, E>(
+        &self,
+        mut f: impl FnMut() -> Result<T, E>,
+        retry: impl Fn(&E) -> bool,
+    ) -> Result<T, E> {
+        let mut attempt = 0u8;
+        loop {
+            thread::sleep(self.delay(attempt));
+            attempt += 1;
+            match f() {
+                Err(err) if attempt < self.max_attempts && retry(&err) => continue,
+                res => return res,
+            }
+        }
+    }
+}
+
+fn is_transient_witness_error(err: &Option<ResolverError>) -> bool {
+    err.as_ref().is_some_and(ResolverError::is_transient)
+}
+
+/// Resolver wrapper retrying failed requests according to [`RetryPolicy`].
+pub struct RetryingResolver<R: RgbResolver> {
+    inner: R,
+    policy: RetryPolicy,
+}
+
+impl<R: RgbResolver> RetryingResolver<R> {
+    pub fn new(inner: R, policy: RetryPolicy) -> Self { RetryingResolver { inner, policy } }
+
+    pub fn policy(&self) -> RetryPolicy { self.policy }
+}
+
+impl<R: RgbResolver> RgbResolver for RetryingResolver<R> {
+    fn check(&self, chain: &ChainParams) -> Result<(), ResolverError> {
+        self.policy
+            .run(|| self.inner.check(chain), ResolverError::is_transient)
+    }
+
+    fn resolve_tip_height(&self) -> Result<u32, ResolverError> {
+        self.policy
+            .run(|| self.inner.resolve_tip_height(), ResolverError::is_transient)
+    }
+
+    fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, ResolverError> {
+        let inner = &mut self.inner;
+        self.policy
+            .run(|| inner.resolve_height(txid), ResolverError::is_transient)
+    }
+
+    fn resolve_pub_witness(&self, txid: Txid) -> Result<Tx, Option<ResolverError>> {
+        // `None` means the transaction is unknown, which is not a network error
+        self.policy
+            .run(|| self.inner.resolve_pub_witness(txid), is_transient_witness_error)
+    }
+
+    fn resolve_status(&mut self, txid: Txid) -> Result<TxStatus, ResolverError> {
+        let inner = &mut self.inner;
+        self.policy
+            .run(|| inner.resolve_status(txid), ResolverError::is_transient)
+    }
+
+    fn resolve_spv_proof(&self, txid: Txid, height: u32) -> Result<SpvProof, ResolverError> {
+        self.policy
+            .run(|| self.inner.resolve_spv_proof(txid, height), ResolverError::is_transient)
+    }
+
+    fn resolve_headers(&self, start: u32, count: u32) -> Result<Vec<BlockHeader>, ResolverError> {
+        self.policy
+            .run(|| self.inner.resolve_headers(start, count), ResolverError::is_transient)
+    }
+
+    fn resolve_pub_witnesses(&self, txids: &[Txid]) -> Vec<Result<Tx, Option<ResolverError>>> {
+        // The batch is requested once; only the failed transactions are retried
+        self.inner
+            .resolve_pub_witnesses(txids)
+            .into_iter()
+            .zip(txids)
+            .map(|(res, txid)| match res {
+                Err(Some(err)) if err.is_transient() && self.policy.max_attempts > 1 => self
+                    .policy
+                    .run(|| self.inner.resolve_pub_witness(*txid), is_transient_witness_error),
+                res => res,
+            })
+            .collect()
+    }
+
+    fn is_cached(&self, call: ResolverCall, txid: Txid) -> bool { self.inner.is_cached(call, txid) }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::resolvers::NETWORK_MISMATCH;
+
+    struct Failing {
+        error: ResolverError,
+        calls: Cell<u8>,
+    }
+
+    impl RgbResolver for Failing {
+        fn check(&self, _chain: &ChainParams) -> Result<(), ResolverError> {
+            self.calls.set(self.calls.get() + 1);
+            Err(self.error.clone())
+        }
+        fn resolve_tip_height(&self) -> Result<u32, ResolverError> { unreachable!() }
+        fn resolve_height(&mut self, _txid: Txid) -> Result<WitnessAnchor, ResolverError> {
+            unreachable!()
+        }
+        fn resolve_pub_witness(&self, _txid: Txid) -> Result<Tx, Option<ResolverError>> {
+            self.calls.set(self.calls.get() + 1);
+            Err(Some(self.error.clone()))
+        }
+    }
+
+    fn retrying(error: ResolverError) -> RetryingResolver<Failing> {
+        let policy = RetryPolicy {
+            backoff: Duration::ZERO,
+            ..default!()
+        };
+        RetryingResolver::new(Failing { error, calls: Cell::new(0) }, policy)
+    }
+
+    #[test]
+    fn retries_transient() {
+        let resolver = retrying(s!("connection refused").into());
+        assert!(resolver.check(&ChainParams::mainnet()).is_err());
+        assert_eq!(resolver.inner.calls.get(), 3);
+
+        let resolver = retrying(s!("connection refused").into());
+        assert_eq!(resolver.resolve_pub_witnesses(&[Txid::coinbase()]).len(), 1);
+        assert_eq!(resolver.inner.calls.get(), 4);
+    }
+
+    #[test]
+    fn network_mismatch_not_retried() {
+        let resolver = retrying(ResolverError::permanent(NETWORK_MISMATCH));
+        assert_eq!(
+            resolver.check(&ChainParams::mainnet()),
+            Err(ResolverError::Permanent(NETWORK_MISMATCH.to_owned()))
+        );
+        assert_eq!(resolver.inner.calls.get(), 1);
+    }
+
+    #[test]
+    fn permanent_not_retried() {
+        let error = ResolverError::permanent("cannot deserialize raw TX - invalid data");
+        let resolver = retrying(error.clone());
+        assert_eq!(resolver.resolve_pub_witness(Txid::coinbase()), Err(Some(error.clone())));
+        assert_eq!(resolver.inner.calls.get(), 1);
+
+        let resolver = retrying(error.clone());
+        assert_eq!(resolver.resolve_pub_witnesses(&[Txid::coinbase()]), vec![Err(Some(error))]);
+        assert_eq!(resolver.inner.calls.get(), 1);
+    }
+}
@@ -0,0 +1,503 @@
+// RGB smart contracts for Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::str::FromStr;
+
+use amplify::ByteArray;
+use bp::{BlockHash, BlockHeader, Tx};
+use bpstd::Txid;
+use rgbstd::{WitnessAnchor, WitnessOrd, WitnessPos};
+use sha2::{Digest, Sha256};
+
+use super::{
+    pos_timestamp, ChainParams, PowParams, ResolverCall, ResolverError, RgbResolver, TxStatus,
+};
+
+/// Maximal difference between timestamps of the last block of a difficulty
+/// period and the first block of the next one under BIP-94.
+const MAX_TIMEWARP: u32 = 600;
+
+/// Simplified payment verification (SPV) proof of a transaction inclusion into
+/// a block.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SpvProof {
+    /// Height of the block containing the transaction.
+    pub height: u32,
+    /// Header of the block containing the transaction.
+    pub header: BlockHeader,
+    /// Position of the transaction in the block.
+    pub pos: u32,
+    /// Merkle branch from the transaction up to the merkle root, in the
+    /// internal byte order.
+    pub merkle: Vec<[u8; 32]>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum SpvError {
+    /// merkle proof for transaction {0} doesn't match merkle root of block {1}.
+    MerkleMismatch(Txid, BlockHash),
+
+    /// header of block {0} doesn't satisfy its proof of work.
+    InvalidPow(BlockHash),
+
+    /// mining information for transaction {0} doesn't match its SPV proof.
+    AnchorMismatch(Txid),
+
+    /// block {1} containing transaction {0} is not a part of the validated
+    /// header chain.
+    NotInChain(Txid, BlockHash),
+
+    /// block at height {0} doesn't match SPV checkpoint.
+    CheckpointMismatch(u32),
+
+    /// block at height {0} is below SPV checkpoint and can't be validated.
+    BelowCheckpoint(u32),
+
+    /// resolver hasn't provided header of the block at height {0}.
+    MissingHeader(u32),
+
+    /// block at height {0} doesn't connect to the previous block.
+    BrokenChain(u32),
+
+    /// block at height {0} has difficulty target not matching the consensus
+    /// rules.
+    InvalidTarget(u32),
+
+    /// block at height {0} violates BIP-94 time warp rule.
+    TimeWarp(u32),
+}
+
+impl From<SpvError> for ResolverError {
+    fn from(err: SpvError) -> Self { ResolverError::permanent(err) }
+}
+
+impl SpvProof {
+    /// Computes merkle root from the transaction id and the merkle branch.
+    pub fn merkle_root(&self, txid: Txid) -> [u8; 32] {
+        let mut hash = txid.to_byte_array();
+        let mut pos = self.pos;
+        for node in &self.merkle {
+            let mut engine = Sha256::new();
+            if pos & 1 == 0 {
+                engine.update(hash);
+                engine.update(node);
+            } else {
+                engine.update(node);
+                engine.update(hash);
+            }
+            hash = Sha256::digest(engine.finalize()).into();
+            pos >>= 1;
+        }
+        hash
+    }
+
+    /// Verifies that the transaction is committed into the block header, and
+    /// that the block header satisfies proof of work it claims.
+    ///
+    /// This doesn't check that the block is a part of a valid chain; this is
+    /// done by [`SpvResolver`].
+    pub fn verify(&self, txid: Txid) -> Result<(), SpvError> {
+        if self.merkle_root(txid) != self.header.merkle_root.to_byte_array() {
+            return Err(SpvError::MerkleMismatch(txid, self.header.block_hash()));
+        }
+        check_pow(&self.header)
+    }
+
+    /// Verifies the proof with [`SpvProof::verify`] and checks that it matches
+    /// mining information of the transaction.
+    pub fn verify_anchor(&self, txid: Txid, pos: WitnessPos) -> Result<(), SpvError> {
+        self.verify(txid)?;
+        if self.height != u32::from(pos.height()) ||
+            i64::from(self.header.time) != pos_timestamp(pos)
+        {
+            return Err(SpvError::AnchorMismatch(txid));
+        }
+        Ok(())
+    }
+}
+
+fn check_pow(header: &BlockHeader) -> Result<(), SpvError> {
+    let block_hash = header.block_hash();
+    let mut hash = block_hash.to_byte_array();
+    hash.reverse();
+    if hash > pow_target(header.bits) {
+        return Err(SpvError::InvalidPow(block_hash));
+    }
+    Ok(())
+}
+
+/// Converts compact target representation into a big-endian 256-bit number.
+fn pow_target(bits: u32) -> [u8; 32] {
+    let mut target = [0u8; 32];
+    let exp = (bits >> 24) as isize;
+    // Negative and overflowing targets are invalid
+    if bits & 0x0080_0000 != 0 || exp > 32 {
+        return target;
+    }
+    let mantissa = (bits & 0x007f_ffff).to_be_bytes();
+    for (i, byte) in mantissa[1..].iter().enumerate() {
+        let idx = 32 - exp + i as isize;
+        if (0..32).contains(&idx) {
+            target[idx as usize] = *byte;
+        }
+    }
+    target
+}
+
+/// Converts big-endian number into the compact target representation.
+fn compact_target(target: &[u8]) -> u32 {
+    let Some(pos) = target.iter().position(|byte| *byte != 0) else {
+        return 0;
+    };
+    let digits = &target[pos..];
+    let mut size = digits.len() as u32;
+    let mut mantissa = digits
+        .iter()
+        .take(3)
+        .fold(0u32, |acc, byte| (acc << 8) | *byte as u32) <<
+        (8 * 3usize.saturating_sub(digits.len()));
+    if mantissa & 0x0080_0000 != 0 {
+        mantissa >>= 8;
+        size += 1;
+    }
+    mantissa | (size << 24)
+}
+
+/// Computes difficulty target for the first block of a new difficulty period,
+/// which started with a block mined at `first_time`, ended with a block mined
+/// at `last_time` and used `bits` as its target.
+fn retarget(pow: &PowParams, first_time: u32, last_time: u32, bits: u32) -> u32 {
+    let timespan = pow.timespan();
+    let actual = (last_time as i64 - first_time as i64)
+        .clamp(timespan as i64 / 4, timespan as i64 * 4) as u64;
+
+    // Computing `target * actual / timespan` using 320-bit number, which can't
+    // overflow
+    let mut scaled = [0u8; 40];
+    let mut carry = 0u64;
+    for (i, byte) in pow_target(bits).iter().enumerate().rev() {
+        let value = *byte as u64 * actual + carry;
+        scaled[i + 8] = value as u8;
+        carry = value >> 8;
+    }
+    for byte in scaled[..8].iter_mut().rev() {
+        *byte = carry as u8;
+        carry >>= 8;
+    }
+    let mut rem = 0u64;
+    for byte in &mut scaled {
+        let value = (rem << 8) | *byte as u64;
+        *byte = (value / timespan as u64) as u8;
+        rem = value % timespan as u64;
+    }
+
+    if scaled[..8].iter().any(|byte| *byte != 0) || scaled[8..] > pow_target(pow.limit)[..] {
+        return pow.limit;
+    }
+    compact_target(&scaled)
+}
+
+/// Chain of validated block headers starting from a checkpoint.
+struct HeaderChain {
+    pow: PowParams,
+    checkpoint: BlockHash,
+    /// Height of the checkpoint.
+    start: u32,
+    /// Hashes of the validated blocks, starting from the checkpoint.
+    hashes: Vec<BlockHash>,
+    /// Headers of the current difficulty period, starting from its first
+    /// block.
+    period: Vec<BlockHeader>,
+}
+
+impl HeaderChain {
+    fn next_height(&self) -> u32 { self.start + self.hashes.len() as u32 }
+
+    fn block_hash(&self, height: u32) -> Option<BlockHash> {
+        let idx = height.checked_sub(self.start)?;
+        self.hashes.get(idx as usize).copied()
+    }
+
+    /// Computes difficulty target which must be used by the next block.
+    fn expected_bits(&self, header: &BlockHeader) -> u32 {
+        let pow = &self.pow;
+        let first = self.period.first().expect("chain always has checkpoint header");
+        let last = self.period.last().expect("chain always has checkpoint header");
+
+        if self.next_height() % pow.interval != 0 {
+            if !pow.min_difficulty_blocks {
+                return last.bits;
+            }
+            if header.time > last.time + pow.spacing * 2 {
+                return pow.limit;
+            }
+            // Return the last target which wasn't set by the special
+            // min-difficulty rule
+            return self
+                .period
+                .iter()
+                .rev()
+                .find(|header| header.bits != pow.limit)
+                .unwrap_or(first)
+                .bits;
+        }
+
+        if !pow.retargeting {
+            return last.bits;
+        }
+        let bits = if pow.bip94 { first.bits } else { last.bits };
+        retarget(pow, first.time, last.time, bits)
+    }
+
+    fn push(&mut self, header: BlockHeader) -> Result<(), SpvError> {
+        let height = self.next_height();
+        let block_hash = header.block_hash();
+        match self.hashes.last() {
+            None if block_hash != self.checkpoint => {
+                return Err(SpvError::CheckpointMismatch(height));
+            }
+            None => {}
+            Some(prev_hash) => {
+                if header.prev_block_hash != *prev_hash {
+                    return Err(SpvError::BrokenChain(height));
+                }
+                if header.bits != self.expected_bits(&header) {
+                    return Err(SpvError::InvalidTarget(height));
+                }
+                let last = self.period.last().expect("chain always has checkpoint header");
+                if self.pow.bip94 &&
+                    height % self.pow.interval == 0 &&
+                    header.time + MAX_TIMEWARP < last.time
+                {
+                    return Err(SpvError::TimeWarp(height));
+                }
+                check_pow(&header)?;
+            }
+        }
+        if height % self.pow.interval == 0 {
+            self.period.clear();
+        }
+        self.period.push(header);
+        self.hashes.push(block_hash);
+        Ok(())
+    }
+}
+
+/// Resolver wrapper verifying SPV proofs for all mined witness transactions
+/// reported by the inner resolver.
+///
+/// Besides the proof itself, the resolver checks that the block containing the
+/// witness is a part of a chain of headers, each having a valid proof of work
+/// and difficulty target, which starts at the chain checkpoint (or the genesis
+/// block, if no checkpoint is given).
+pub struct SpvResolver<R: RgbResolver> {
+    inner: R,
+    chain: HeaderChain,
+}
+
+impl<R: RgbResolver> SpvResolver<R> {
+    pub fn new(inner: R, chain: &ChainParams) -> Result<Self, String> {
+        let pow = chain.pow;
+        let (start, hash) = match &chain.checkpoint {
+            Some(checkpoint) => (checkpoint.height, checkpoint.hash.as_str()),
+            None => (0, chain.genesis_hash.as_str()),
+        };
+        if pow.interval == 0 || pow.spacing == 0 {
+            return Err(s!("invalid proof-of-work parameters of the chain"));
+        }
+        if start % pow.interval != 0 {
+            return Err(format!(
+                "SPV checkpoint at height {start} is not the first block of a difficulty period"
+            ));
+        }
+        let checkpoint =
+            BlockHash::from_str(hash).map_err(|e| format!("invalid SPV checkpoint hash - {e}"))?;
+        Ok(SpvResolver {
+            inner,
+            chain: HeaderChain {
+                pow,
+                checkpoint,
+                start,
+                hashes: vec![],
+                period: vec![],
+            },
+        })
+    }
+
+    /// Returns hash of the block at `height` in the validated header chain,
+    /// extending the chain with headers provided by the inner resolver if
+    /// necessary.
+    fn validated_hash(&mut self, height: u32) -> Result<BlockHash, ResolverError> {
+        if height < self.chain.start {
+            return Err(SpvError::BelowCheckpoint(height).into());
+        }
+        while self.chain.next_height() <= height {
+            let start = self.chain.next_height();
+            let count = (height - start + 1).min(self.chain.pow.interval);
+            let headers = self.inner.resolve_headers(start, count)?;
+            if headers.is_empty() {
+                return Err(SpvError::MissingHeader(start).into());
+            }
+            for header in headers.into_iter().take(count as usize) {
+                self.chain.push(header)?;
+            }
+        }
+        Ok(self
+            .chain
+            .block_hash(height)
+            .expect("chain is extended up to the height"))
+    }
+}
+
+impl<R: RgbResolver> RgbResolver for SpvResolver<R> {
+    fn check(&self, chain: &ChainParams) -> Result<(), ResolverError> { self.inner.check(chain) }
+
+    fn resolve_tip_height(&self) -> Result<u32, ResolverError> { self.inner.resolve_tip_height() }
+
+    fn resolve_height(&mut self, txid: Txid) -> Result<WitnessAnchor, ResolverError> {
+        let anchor = self.inner.resolve_height(txid)?;
+        let WitnessOrd::OnChain(pos) = anchor.witness_ord else {
+            return Ok(anchor);
+        };
+        let height = u32::from(pos.height());
+        let proof = self.inner.resolve_spv_proof(txid, height)?;
+        proof.verify_anchor(txid, pos)?;
+        let block_hash = proof.header.block_hash();
+        if self.validated_hash(height)? != block_hash {
+            return Err(SpvError::NotInChain(txid, block_hash).into());
+        }
+        Ok(anchor)
+    }
+
+    fn resolve_pub_witness(&self, txid: Txid) -> Result<Tx, Option<ResolverError>> {
+        self.inner.resolve_pub_witness(txid)
+    }
+
+    fn resolve_status(&mut self, txid: Txid) -> Result<TxStatus, ResolverError> {
+        let status = self.inner.resolve_status(txid)?;
+        if let TxStatus::Mined { height, .. } = status {
+            // Mining information is verified against the header chain
+            match self.resolve_height(txid)?.witness_ord {
+                WitnessOrd::OnChain(pos) if u32::from(pos.height()) == height => {}
+                _ => return Err(SpvError::AnchorMismatch(txid).into()),
+            }
+        }
+        Ok(status)
+    }
+
+    fn resolve_spv_proof(&self, txid: Txid, height: u32) -> Result<SpvProof, ResolverError> {
+        self.inner.resolve_spv_proof(txid, height)
+    }
+
+    fn resolve_headers(&self, start: u32, count: u32) -> Result<Vec<BlockHeader>, ResolverError> {
+        self.inner.resolve_headers(start, count)
+    }
+
+    fn resolve_pub_witnesses(&self, txids: &[Txid]) -> Vec<Result<Tx, Option<ResolverError>>> {
+        self.inner.resolve_pub_witnesses(txids)
+    }
+
+    fn is_cached(&self, call: ResolverCall, txid: Txid) -> bool {
+        // Cached mining information is still verified with an SPV proof
+        // requested from the backend
+        call != ResolverCall::Height && self.inner.is_cached(call, txid)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Test vectors are taken from Bitcoin Core `pow_tests.cpp`
+    #[test]
+    fn retarget_mainnet() {
+        let pow = PowParams::mainnet();
+        assert_eq!(retarget(&pow, 1261130161, 1262152739, 0x1d00ffff), 0x1d00d86a);
+    }
+
+    #[test]
+    fn retarget_pow_limit() {
+        let pow = PowParams::mainnet();
+        assert_eq!(retarget(&pow, 1231006505, 1233061996, 0x1d00ffff), 0x1d00ffff);
+    }
+
+    #[test]
+    fn retarget_lower_limit_actual() {
+        let pow = PowParams::mainnet();
+        assert_eq!(retarget(&pow, 1279008237, 1279297671, 0x1c05a3f4), 0x1c0168fd);
+    }
+
+    #[test]
+    fn retarget_upper_limit_actual() {
+        let pow = PowParams::mainnet();
+        assert_eq!(retarget(&pow, 1263163443, 1269211443, 0x1c387f6f), 0x1d00e1fd);
+    }
+
+    #[test]
+    fn compact_round_trip() {
+        for bits in [0x1d00ffff, 0x1c05a3f4, 0x1e0377ae, 0x207fffff, 0x1a05db8b] {
+            assert_eq!(compact_target(&pow_target(bits)), bits);
+        }
+    }
+
+    #[test]
+    fn chain_validation() {
+        let pow = PowParams::regtest();
+        let genesis = BlockHeader {
+            version: 1,
+            prev_block_hash: BlockHash::from([0u8; 32]),
+            merkle_root: [0x11; 32].into(),
+            time: 1296688602,
+            bits: pow.limit,
+            nonce: 0,
+        };
+        let mut chain = HeaderChain {
+            pow,
+            checkpoint: genesis.block_hash(),
+            start: 0,
+            hashes: vec![],
+            period: vec![],
+        };
+        chain.push(genesis).unwrap();
+
+        let mut next = BlockHeader {
+            prev_block_hash: genesis.block_hash(),
+            time: genesis.time + 600,
+            ..genesis
+        };
+        while check_pow(&next).is_err() {
+            next.nonce += 1;
+        }
+
+        let mut wrong_prev = next;
+        wrong_prev.prev_block_hash = BlockHash::from([1u8; 32]);
+        assert_eq!(chain.push(wrong_prev), Err(SpvError::BrokenChain(1)));
+
+        let mut wrong_bits = next;
+        wrong_bits.bits = 0x1d00ffff;
+        assert_eq!(chain.push(wrong_bits), Err(SpvError::InvalidTarget(1)));
+
+        chain.push(next).unwrap();
+        assert_eq!(chain.block_hash(1), Some(next.block_hash()));
+        assert_eq!(chain.block_hash(2), None);
+    }
+}
@@ -0,0 +1,267 @@
+// RGB smart contracts for Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::str::FromStr;
+
+use amplify::hex::{FromHex, ToHex};
+use bp::{BlockHeader, ConsensusDecode, ConsensusEncode, Tx};
+use bpstd::Txid;
+use rgbstd::{WitnessAnchor, WitnessOrd, WitnessPos, XWitnessId};
+
+use super::SpvProof;
+
+/// Collection of witness transactions and their mining information, which can
+/// be stored in a file.
+///
+/// The file has a line-based text format, where each line contains one of the
+/// following entries:
+/// - `genesis <BLOCK_HASH>`: hash of the genesis block of the network;
+/// - `tip <HEIGHT>`: height of the blockchain tip at the moment of export;
+/// - `tx <HEX>`: consensus-serialized transaction;
+/// - `anchor <TXID> <HEIGHT> <TIMESTAMP>`: mining information for a
+///   transaction;
+/// - `proof <TXID> <HEIGHT> <POS> <HEADER_HEX> <MERKLE_BRANCH>`: SPV proof for
+///   a transaction, where the merkle branch is a comma-separated list of
+///   hashes, or `-` if the branch is empty;
+/// - `header <HEIGHT> <HEADER_HEX>`: block header, which is a part of the
+///   header chain used for SPV validation of the proofs.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct WitnessBundle {
+    pub genesis_hash: Option<String>,
+    pub tip_height: Option<u32>,
+    pub txes: HashMap<Txid, Tx>,
+    pub anchors: HashMap<Txid, WitnessAnchor>,
+    pub proofs: HashMap<Txid, SpvProof>,
+    pub headers: BTreeMap<u32, BlockHeader>,
+}
+
+impl WitnessBundle {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn add_tx(&mut self, tx: Tx) { self.txes.insert(tx.txid(), tx); }
+
+    pub fn add_anchor(&mut self, anchor: WitnessAnchor) -> bool {
+        let XWitnessId::Bitcoin(txid) = anchor.witness_id else {
+            return false;
+        };
+        if !matches!(anchor.witness_ord, WitnessOrd::OnChain(_)) {
+            return false;
+        }
+        self.anchors.insert(txid, anchor);
+        true
+    }
+}
+
+impl Display for WitnessBundle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(genesis_hash) = &self.genesis_hash {
+            writeln!(f, "genesis {genesis_hash}")?;
+        }
+        if let Some(tip_height) = self.tip_height {
+            writeln!(f, "tip {tip_height}")?;
+        }
+        for tx in self.txes.values() {
+            writeln!(f, "tx {}", tx.consensus_serialize().to_hex())?;
+        }
+        for (txid, anchor) in &self.anchors {
+            if let WitnessOrd::OnChain(pos) = anchor.witness_ord {
+                writeln!(f, "anchor {txid} {} {}", u32::from(pos.height()), pos_timestamp(pos))?;
+            }
+        }
+        for (txid, proof) in &self.proofs {
+            let merkle = if proof.merkle.is_empty() {
+                s!("-")
+            } else {
+                proof
+                    .merkle
+                    .iter()
+                    .map(|node| node.to_hex())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            };
+            writeln!(
+                f,
+                "proof {txid} {} {} {} {merkle}",
+                proof.height,
+                proof.pos,
+                proof.header.consensus_serialize().to_hex()
+            )?;
+        }
+        for (height, header) in &self.headers {
+            writeln!(f, "header {height} {}", header.consensus_serialize().to_hex())?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns timestamp of the block mining a witness.
+///
+/// [`WitnessPos`] doesn't expose the timestamp, thus it is taken from its
+/// `{height}@{timestamp}` string representation.
+pub(crate) fn pos_timestamp(pos: WitnessPos) -> i64 {
+    pos.to_string()
+        .rsplit_once('@')
+        .and_then(|(_, timestamp)| i64::from_str(timestamp).ok())
+        .expect("WitnessPos is displayed as height@timestamp")
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display("invalid witness bundle entry at line {0}")]
+pub struct WitnessBundleError(pub usize);
+
+impl FromStr for WitnessBundle {
+    type Err = WitnessBundleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bundle = WitnessBundle::default();
+        for (no, line) in s.lines().enumerate() {
+            let err = WitnessBundleError(no + 1);
+            match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [] => {}
+                ["genesis", hash] => {
+                    bundle.genesis_hash = Some(hash.to_string());
+                }
+                ["tip", height] => {
+                    bundle.tip_height = Some(u32::from_str(height).map_err(|_| err)?);
+                }
+                ["tx", hex] => {
+                    let data = Vec::<u8>::from_hex(hex).map_err(|_| err)?;
+                    let tx = Tx::consensus_deserialize(data).map_err(|_| err)?;
+                    bundle.add_tx(tx);
+                }
+                ["anchor", txid, height, timestamp] => {
+                    let txid = Txid::from_str(txid).map_err(|_| err)?;
+                    let height = u32::from_str(height).map_err(|_| err)?;
+                    let timestamp = i64::from_str(timestamp).map_err(|_| err)?;
+                    let pos = WitnessPos::new(height, timestamp).ok_or(err)?;
+                    bundle.anchors.insert(txid, WitnessAnchor {
+                        witness_ord: WitnessOrd::OnChain(pos),
+                        witness_id: XWitnessId::Bitcoin(txid),
+                    });
+                }
+                ["proof", txid, height, pos, header, merkle] => {
+                    let txid = Txid::from_str(txid).map_err(|_| err)?;
+                    let header = Vec::<u8>::from_hex(header).map_err(|_| err)?;
+                    let merkle = match *merkle {
+                        "-" => vec![],
+                        list => list
+                            .split(',')
+                            .map(|node| {
+                                Vec::<u8>::from_hex(node)
+                                    .ok()
+                                    .and_then(|node| <[u8; 32]>::try_from(node).ok())
+                                    .ok_or(err)
+                            })
+                            .collect::<Result<_, _>>()?,
+                    };
+                    bundle.proofs.insert(txid, SpvProof {
+                        height: u32::from_str(height).map_err(|_| err)?,
+                        header: BlockHeader::consensus_deserialize(header).map_err(|_| err)?,
+                        pos: u32::from_str(pos).map_err(|_| err)?,
+                        merkle,
+                    });
+                }
+                ["header", height, header] => {
+                    let height = u32::from_str(height).map_err(|_| err)?;
+                    let header = Vec::<u8>::from_hex(header).map_err(|_| err)?;
+                    let header = BlockHeader::consensus_deserialize(header).map_err(|_| err)?;
+                    bundle.headers.insert(height, header);
+                }
+                _ => return Err(err),
+            }
+        }
+        Ok(bundle)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, process};
+
+    use super::*;
+
+    /// Transaction spending a null outpoint to no outputs.
+    const TX: &str = "0100000001000000000000000000000000000000000000000000000000000000000000\
+                      0000ffffffff00ffffffff0000000000";
+
+    #[test]
+    fn save_load() {
+        let tx = Tx::consensus_deserialize(Vec::<u8>::from_hex(TX).unwrap()).unwrap();
+        let txid = tx.txid();
+        let header = BlockHeader {
+            version: 1,
+            prev_block_hash: [0x22; 32].into(),
+            merkle_root: [0x11; 32].into(),
+            time: 1296688602,
+            bits: 0x207fffff,
+            nonce: 2,
+        };
+        let mut bundle = WitnessBundle {
+            genesis_hash: Some(header.block_hash().to_string()),
+            tip_height: Some(10),
+            ..default!()
+        };
+        bundle.add_tx(tx);
+        assert!(bundle.add_anchor(WitnessAnchor {
+            witness_ord: WitnessOrd::OnChain(WitnessPos::new(1, 1296688602).unwrap()),
+            witness_id: XWitnessId::Bitcoin(txid),
+        }));
+        assert!(!bundle.add_anchor(WitnessAnchor {
+            witness_ord: WitnessOrd::OffChain,
+            witness_id: XWitnessId::Bitcoin(txid),
+        }));
+        bundle.proofs.insert(txid, SpvProof {
+            height: 1,
+            header,
+            pos: 1,
+            merkle: vec![[0x33; 32], [0x44; 32]],
+        });
+        bundle.headers.insert(0, header);
+        bundle.headers.insert(1, header);
+
+        let path = env::temp_dir().join(format!("rgb-witness-bundle-{}", process::id()));
+        bundle.save(&path).unwrap();
+        assert_eq!(WitnessBundle::load(&path).unwrap(), bundle);
+        fs::remove_file(path).unwrap();
+
+        let empty = WitnessBundle::default();
+        assert_eq!(WitnessBundle::from_str(&empty.to_string()), Ok(empty));
+    }
+
+    #[test]
+    fn invalid_entry() {
+        assert_eq!(WitnessBundle::from_str("tip 10\nheader 1 00"), Err(WitnessBundleError(2)));
+        assert_eq!(WitnessBundle::from_str("proof"), Err(WitnessBundleError(1)));
+    }
+}
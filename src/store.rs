@@ -19,27 +19,35 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::error::Error;
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
 
-use bpstd::XpubDerivable;
+use bp::dbc::tapret::TapretCommitment;
+use bpstd::{NormalIndex, Terminal, XpubDerivable};
 use bpwallet::{StoreError, Wallet, WalletDescr};
 use psrgbt::{Psbt, PsbtMeta};
 use rgbstd::containers::Transfer;
 use rgbstd::interface::{AmountChange, IfaceOp, IfaceRef};
-use rgbstd::persistence::fs::StoreFs;
+use rgbstd::persistence::fs::{LoadFs, StoreFs};
 use rgbstd::persistence::{
     IndexProvider, MemIndex, MemStash, MemState, StashProvider, StateProvider, Stock,
 };
+use rgbstd::resolvers::ResolveHeight;
+use rgbstd::validation::{ResolveWitness, WitnessResolverError};
+use rgbstd::{
+    BundleId, ContractHistory, OpId, Operation, WitnessAnchor, WitnessOrd, XWitnessTx,
+};
 
 use super::{
-    CompletionError, CompositionError, ContractId, DescriptorRgb, PayError, TransferParams,
-    WalletError, WalletProvider, WalletStock, XWitnessId,
+    recorded_witness_ords, CompletionError, CompositionError, ContractId, DescriptorRgb, PayError,
+    SyncReport, TransferParams, Txid, WalletError, WalletProvider, WalletStock, WitnessStatus,
+    XWitnessId,
 };
 use crate::invoice::RgbInvoice;
+use crate::pay::finalize_witness;
 
 pub trait Store {
     type Err: Error;
@@ -230,6 +238,278 @@ where
         self.wallet.transfer(&mut self.stock, invoice, psbt)
     }
 
+    /// Replaces the unsigned witness transaction of a transfer in the stash
+    /// with the signed and finalized one from the `psbt`; see
+    /// [`finalize_witness`].
+    #[allow(clippy::result_large_err)]
+    pub fn finalize_transfer(&mut self, psbt: &Psbt) -> Result<(), CompletionError>
+    where
+        S: LoadFs,
+        H: LoadFs,
+        P: LoadFs,
+    {
+        self.update_providers(|stash, _, _| finalize_witness(stash, psbt))
+    }
+
+    /// Runs `f` over the providers of the stock, which [`Stock`] doesn't give
+    /// mutable access to. The providers are re-read from the stock persisted
+    /// at the stock path, which is updated beforehand, and replace the stock
+    /// only if `f` succeeds.
+    fn update_providers<T, E: From<String>>(
+        &mut self,
+        f: impl FnOnce(&mut S, &mut H, &mut P) -> Result<T, E>,
+    ) -> Result<T, E>
+    where
+        S: LoadFs,
+        H: LoadFs,
+        P: LoadFs,
+    {
+        let path = &self.stock_path;
+        self.stock.store(path).map_err(|e| e.to_string())?;
+        let mut stash = S::load(path).map_err(|e| e.to_string())?;
+        let mut state = H::load(path).map_err(|e| e.to_string())?;
+        let mut index = P::load(path).map_err(|e| e.to_string())?;
+        let res = f(&mut stash, &mut state, &mut index)?;
+        self.stock = Stock::with(stash, state, index);
+        self.stock_dirty = true;
+        Ok(res)
+    }
+
+    /// Re-resolves mining status of the witness transactions known to the
+    /// stash which are not mined at or below `final_height`, persisting the
+    /// updated status next to the stock.
+    ///
+    /// If some of the witnesses got mined, re-mined or re-orged out, the
+    /// state of the contracts they are witnessing is rebuilt from the stash,
+    /// such that the global state ordering follows the new mining status.
+    #[allow(clippy::result_large_err)]
+    pub fn sync_witnesses<R: ResolveHeight>(
+        &mut self,
+        resolver: &mut R,
+        final_height: u32,
+    ) -> Result<SyncReport, WalletError>
+    where
+        S: LoadFs,
+        H: LoadFs,
+        P: LoadFs,
+    {
+        let stash = self.stock.as_stash_provider();
+        let state = self.stock.as_state_provider();
+        let mut recorded = BTreeMap::new();
+        for genesis in stash.geneses().map_err(|err| err.to_string())? {
+            let history = state
+                .contract_state(genesis.contract_id())
+                .map_err(|err| err.to_string())?;
+            if let Some(history) = history {
+                recorded.extend(recorded_witness_ords(history)?);
+            }
+        }
+        let witnesses = stash
+            .witness_ids()
+            .map_err(|err| err.to_string())?
+            .filter_map(|witness_id| match witness_id {
+                XWitnessId::Bitcoin(txid) => Some((txid, recorded.get(&witness_id).copied())),
+                XWitnessId::Liquid(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let mut status = WitnessStatus::load(&self.stock_path)?;
+        let report = status.sync(resolver, witnesses, final_height);
+        status.store()?;
+
+        let changed = report
+            .confirmed
+            .keys()
+            .chain(&report.reorged)
+            .map(|txid| XWitnessId::Bitcoin(*txid))
+            .collect::<BTreeSet<_>>();
+        if !changed.is_empty() {
+            self.reorder_state(resolver, &status, &changed)
+                .map_err(WalletError::Stock)?;
+            self.stock_dirty = true;
+        }
+        Ok(report)
+    }
+
+    /// Rebuilds the history of contracts having bundles witnessed by one of
+    /// the `changed` witnesses, ordering operations according to the mining
+    /// status from `status`. Bundles of the abandoned witnesses are excluded
+    /// from the history.
+    fn reorder_state<R: ResolveHeight>(
+        &mut self,
+        resolver: &mut R,
+        status: &WitnessStatus,
+        changed: &BTreeSet<XWitnessId>,
+    ) -> Result<(), String>
+    where
+        S: LoadFs,
+        H: LoadFs,
+        P: LoadFs,
+    {
+        let stash = self.stock.as_stash_provider();
+        let index = self.stock.as_index_provider();
+
+        let mut contracts = BTreeMap::<ContractId, Vec<(BundleId, XWitnessId)>>::new();
+        for bundle_id in stash.bundle_ids().map_err(|err| err.to_string())? {
+            let (witness_id, contract_id) =
+                index.bundle_info(bundle_id).map_err(|err| err.to_string())?;
+            contracts
+                .entry(contract_id)
+                .or_default()
+                .push((bundle_id, witness_id));
+        }
+        contracts.retain(|_, bundles| bundles.iter().any(|(_, id)| changed.contains(id)));
+        if contracts.is_empty() {
+            return Ok(());
+        }
+
+        self.update_providers(|stash, state, _| {
+            for (contract_id, bundles) in contracts {
+                let history = rebuild_history(stash, resolver, status, contract_id, bundles)?;
+                state
+                    .update_state::<R>(contract_id, |prev| {
+                        *prev = history.clone();
+                        Ok(())
+                    })
+                    .map_err(|err| err.to_string())?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Confirms pending tapret tweaks of the witness transactions which got
+    /// mined, returning the list of such witnesses.
+    pub fn confirm_tapret_tweaks(
+        &mut self,
+        resolver: &mut impl ResolveHeight,
+    ) -> Result<Vec<Txid>, String> {
+        let mut confirmed = vec![];
+        for txid in self.wallet.descriptor().pending_tapret_witnesses() {
+            let anchor = resolver.resolve_height(XWitnessId::Bitcoin(txid))?;
+            if matches!(anchor.witness_ord, WitnessOrd::OnChain(_)) &&
+                self.wallet.descriptor_mut().confirm_tapret_tweak(txid)
+            {
+                confirmed.push(txid);
+            }
+        }
+        if !confirmed.is_empty() {
+            self.wallet_dirty = true;
+        }
+        Ok(confirmed)
+    }
+
+    /// Abandons transfer which witness transaction is not going to be
+    /// broadcast. Removes the pending tapret tweak added by the transfer from
+    /// the wallet descriptor and marks the witness as abandoned, such that it
+    /// is not synchronized anymore.
+    ///
+    /// The history of contracts touched by the transfer is rebuilt without
+    /// the transfer operations, thus the allocations created by it are
+    /// removed from the contract state. The stash keeps the witness bundle,
+    /// however since the witness never spends wallet outputs the allocations
+    /// it has spent remain available to the wallet. The resolver is used to
+    /// order operations of the other witnesses not synchronized yet.
+    ///
+    /// Returns the removed tapret tweak, if any.
+    #[allow(clippy::result_large_err)]
+    pub fn abandon<R: ResolveHeight>(
+        &mut self,
+        witness: Txid,
+        resolver: &mut R,
+    ) -> Result<Option<(Terminal, TapretCommitment)>, WalletError>
+    where
+        S: LoadFs,
+        H: LoadFs,
+        P: LoadFs,
+    {
+        let mut status = WitnessStatus::load(&self.stock_path)?;
+        status.abandon(witness);
+        status.store()?;
+        self.reorder_state(resolver, &status, &bset! { XWitnessId::Bitcoin(witness) })
+            .map_err(WalletError::Stock)?;
+        self.stock_dirty = true;
+        let tweak = self.wallet.descriptor_mut().abandon_tapret_tweak(witness);
+        if tweak.is_some() {
+            self.wallet_dirty = true;
+        }
+        Ok(tweak)
+    }
+
+    /// Recovers tapret tweaks missing in the wallet descriptor from the tapret
+    /// anchors known to the stash. Witness transaction of each anchor is
+    /// resolved and its outputs are matched against scripts derived with the
+    /// anchor tweak from the tapret keychain for indexes up to `max_index`.
+    ///
+    /// Only witnesses present in the wallet transaction cache are considered,
+    /// thus the wallet must be synced before calling this method.
+    ///
+    /// Returns the list of the recovered tweaks.
+    pub fn recover_tapret_tweaks(
+        &mut self,
+        resolver: &impl ResolveWitness,
+        max_index: u16,
+    ) -> Result<Vec<(Terminal, TapretCommitment)>, String> {
+        let keychain = self.wallet.descriptor().seal_keychains().tapret;
+        let taprets = self
+            .stock
+            .as_stash_provider()
+            .taprets()
+            .map_err(|e| e.to_string())?
+            .collect::<Vec<_>>();
+        let wallet_txids = self.wallet.txids().collect::<BTreeSet<_>>();
+
+        let mut recovered = vec![];
+        for (witness_id, tweak) in taprets {
+            let descriptor = self.wallet.descriptor();
+            let known = descriptor
+                .tapret_terminals()
+                .into_iter()
+                .any(|terminal| descriptor.tapret_tweaks(terminal).contains(&tweak));
+            if known {
+                continue;
+            }
+            // Anchors of transfers made by other wallets have witnesses which
+            // neither spend nor create our outputs
+            let XWitnessId::Bitcoin(txid) = witness_id else {
+                continue;
+            };
+            if !wallet_txids.contains(&txid) {
+                continue;
+            }
+            let tx = match resolver.resolve_pub_witness(witness_id) {
+                Ok(XWitnessTx::Bitcoin(tx)) => tx,
+                // Witness was never broadcast, so there is nothing to recover
+                Ok(_) | Err(WitnessResolverError::Unknown(_)) => continue,
+                Err(err) => return Err(err.to_string()),
+            };
+            let taproot = tx
+                .outputs
+                .iter()
+                .map(|out| &out.script_pubkey)
+                .filter(|script| script.is_p2tr())
+                .collect::<Vec<_>>();
+            if taproot.is_empty() {
+                continue;
+            }
+            let terminal = (0..=max_index)
+                .map(|index| Terminal::new(keychain, NormalIndex::from(index)))
+                .find(|terminal| {
+                    descriptor
+                        .derive_with_tapret(*terminal, &tweak)
+                        .map(|script| script.to_script_pubkey())
+                        .is_some_and(|script| taproot.contains(&&script))
+                });
+            if let Some(terminal) = terminal {
+                self.wallet
+                    .descriptor_mut()
+                    .add_tapret_tweak(terminal, tweak.clone())
+                    .map_err(|e| e.to_string())?;
+                self.wallet_dirty = true;
+                recovered.push((terminal, tweak));
+            }
+        }
+        Ok(recovered)
+    }
+
     pub fn store(&self) {
         let r1 = if self.stock_dirty {
             self.stock
@@ -251,6 +531,59 @@ where
     }
 }
 
+/// Rebuilds the history of the contract from the `bundles` known to the stash
+/// together with their witnesses, ordering operations according to the
+/// mining status from `status`; see [`StoredWallet::sync_witnesses`].
+fn rebuild_history<S: StashProvider>(
+    stash: &S,
+    resolver: &mut impl ResolveHeight,
+    status: &WitnessStatus,
+    contract_id: ContractId,
+    bundles: Vec<(BundleId, XWitnessId)>,
+) -> Result<ContractHistory, String> {
+    let genesis = stash.genesis(contract_id).map_err(|err| err.to_string())?;
+    let mut history = ContractHistory::with(genesis.schema_id, contract_id, genesis);
+
+    let mut extension_anchors = BTreeMap::<OpId, WitnessAnchor>::new();
+    for (bundle_id, witness_id) in bundles {
+        if matches!(witness_id, XWitnessId::Bitcoin(txid) if status.is_abandoned(txid)) {
+            continue;
+        }
+        let witness_anchor = match witness_id {
+            XWitnessId::Bitcoin(txid) => match status.status(txid) {
+                Some(witness_ord) => WitnessAnchor {
+                    witness_ord,
+                    witness_id,
+                },
+                None => resolver.resolve_height(witness_id)?,
+            },
+            XWitnessId::Liquid(_) => resolver.resolve_height(witness_id)?,
+        };
+        let bundle = stash.bundle(bundle_id).map_err(|err| err.to_string())?;
+        for transition in bundle.known_transitions.values() {
+            history.add_transition(transition, witness_anchor);
+            for input in &transition.inputs {
+                extension_anchors
+                    .entry(input.prev_out.op)
+                    .and_modify(|anchor| *anchor = (*anchor).min(witness_anchor))
+                    .or_insert(witness_anchor);
+            }
+        }
+    }
+    // extensions are ordered by the first transition spending them, while the
+    // ones not spent by any transition are not part of the state
+    for opid in stash.extension_ids().map_err(|err| err.to_string())? {
+        let Some(witness_anchor) = extension_anchors.get(&opid) else {
+            continue;
+        };
+        let extension = stash.extension(opid).map_err(|err| err.to_string())?;
+        if extension.contract_id == contract_id {
+            history.add_extension(extension, *witness_anchor);
+        }
+    }
+    Ok(history)
+}
+
 impl<K, W: WalletProvider<K>, S: StashProvider, H: StateProvider, P: IndexProvider> Drop
     for StoredWallet<W, K, S, H, P>
 where
@@ -0,0 +1,418 @@
+// RGB smart contracts for Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2024 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use amplify::confinement::{LargeOrdMap, TinyOrdMap, U32};
+use bpstd::{Outpoint, Txid};
+use rgbstd::resolvers::ResolveHeight;
+use rgbstd::{
+    ContractHistory, ContractId, DataState, GlobalOrd, GlobalStateType, SchemaId, WitnessOrd,
+    WitnessPos, XWitnessId,
+};
+use strict_types::encoding::{StrictDecode, StrictEncode, StrictReader, StrictWriter};
+
+use crate::resolvers::pos_timestamp;
+
+/// Last known mining status of witness transactions, persisted between
+/// synchronizations such that re-orgs and new confirmations can be detected.
+///
+/// The file has a line-based text format, where each line is either
+/// `<TXID> <HEIGHT> <TIMESTAMP>` for mined witnesses, `<TXID> -` for
+/// witnesses which are not (or are no longer) mined or `<TXID> abandoned` for
+/// witnesses abandoned by the user, which are never re-resolved.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct WitnessStatus {
+    path: PathBuf,
+    known: BTreeMap<Txid, WitnessOrd>,
+    abandoned: BTreeSet<Txid>,
+}
+
+impl WitnessStatus {
+    pub const FILE_NAME: &'static str = "witness.status";
+
+    /// Loads witness status from the `dir` directory; if the file is absent
+    /// starts with no witnesses known.
+    pub fn load(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let path = dir.as_ref().join(Self::FILE_NAME);
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == ErrorKind::NotFound => empty!(),
+            Err(err) => return Err(err),
+        };
+        let mut known = bmap! {};
+        let mut abandoned = bset! {};
+        for (no, line) in data.lines().enumerate() {
+            let (txid, ord) = parse_line(no + 1, line)
+                .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+            match ord {
+                Some(ord) => {
+                    known.insert(txid, ord);
+                }
+                None => {
+                    abandoned.insert(txid);
+                }
+            }
+        }
+        Ok(WitnessStatus {
+            path,
+            known,
+            abandoned,
+        })
+    }
+
+    pub fn store(&self) -> io::Result<()> {
+        // Write to a temporary file and rename it, so an interrupted write
+        // never leaves a truncated status behind
+        let tmp_path = self.path.with_extension("status.tmp");
+        fs::write(&tmp_path, self.to_string())?;
+        fs::rename(tmp_path, &self.path)
+    }
+
+    pub fn status(&self, txid: Txid) -> Option<WitnessOrd> { self.known.get(&txid).copied() }
+
+    pub fn is_abandoned(&self, txid: Txid) -> bool { self.abandoned.contains(&txid) }
+
+    /// Marks witness as abandoned, i.e. as one which is never going to be
+    /// broadcast, such that its status is not resolved anymore.
+    pub fn abandon(&mut self, txid: Txid) {
+        self.known.remove(&txid);
+        self.abandoned.insert(txid);
+    }
+
+    /// Re-resolves mining status for all `witnesses` which are not final,
+    /// i.e. which are not known to be mined at or below `final_height`, and
+    /// reports the changes compared to the previously known status.
+    ///
+    /// Each witness comes with the mining status recorded in the contract
+    /// state when the witness was accepted, if any (see
+    /// [`recorded_witness_ords`]), which is used as the previous status for
+    /// the witnesses not synchronized before. Witnesses without any previous
+    /// status are recorded but reported only if they are mined.
+    pub fn sync(
+        &mut self,
+        resolver: &mut impl ResolveHeight,
+        witnesses: impl IntoIterator<Item = (Txid, Option<WitnessOrd>)>,
+        final_height: u32,
+    ) -> SyncReport {
+        let mut report = SyncReport::default();
+        for (txid, recorded) in witnesses {
+            if self.is_abandoned(txid) {
+                continue;
+            }
+            let prev = self.status(txid).or(recorded);
+            if let Some(WitnessOrd::OnChain(pos)) = prev {
+                if u32::from(pos.height()) <= final_height {
+                    continue;
+                }
+            }
+            let ord = match resolver.resolve_height(XWitnessId::Bitcoin(txid)) {
+                Ok(anchor) => anchor.witness_ord,
+                Err(err) => {
+                    report.failed.insert(txid, err);
+                    continue;
+                }
+            };
+            match (prev, ord) {
+                (Some(prev), ord) if prev == ord => {}
+                (_, WitnessOrd::OnChain(pos)) => {
+                    report.confirmed.insert(txid, pos);
+                }
+                (Some(WitnessOrd::OnChain(_)), WitnessOrd::OffChain) => {
+                    report.reorged.insert(txid);
+                }
+                (_, WitnessOrd::OffChain) => {}
+            }
+            self.known.insert(txid, ord);
+        }
+        report
+    }
+}
+
+impl Display for WitnessStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (txid, ord) in &self.known {
+            match ord {
+                WitnessOrd::OnChain(pos) => {
+                    writeln!(f, "{txid} {} {}", u32::from(pos.height()), pos_timestamp(*pos))?
+                }
+                WitnessOrd::OffChain => writeln!(f, "{txid} -")?,
+            }
+        }
+        for txid in &self.abandoned {
+            writeln!(f, "{txid} abandoned")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display("invalid witness status entry at line {0}")]
+pub struct WitnessStatusError(pub usize);
+
+/// Parses status file line, returning `None` for abandoned witnesses.
+fn parse_line(no: usize, line: &str) -> Result<(Txid, Option<WitnessOrd>), WitnessStatusError> {
+    let err = WitnessStatusError(no);
+    let parts = line.split_whitespace().collect::<Vec<_>>();
+    let (txid, ord) = match parts.as_slice() {
+        [txid, "abandoned"] => (txid, None),
+        [txid, "-"] => (txid, Some(WitnessOrd::OffChain)),
+        [txid, height, timestamp] => {
+            let height = u32::from_str(height).map_err(|_| err)?;
+            let timestamp = i64::from_str(timestamp).map_err(|_| err)?;
+            (txid, Some(WitnessOrd::OnChain(WitnessPos::new(height, timestamp).ok_or(err)?)))
+        }
+        _ => return Err(err),
+    };
+    Ok((Txid::from_str(txid).map_err(|_| err)?, ord))
+}
+
+/// Collects mining status of the witnesses as it is recorded in the global
+/// state ordering of the contract `history`.
+///
+/// [`ContractHistory`] doesn't expose the ordering, thus it is read from the
+/// strict encoding of the history, which starts with the schema and contract
+/// ids followed by the global state. Witnesses of the operations which don't
+/// define global state have no status recorded.
+pub fn recorded_witness_ords(
+    history: &ContractHistory,
+) -> Result<BTreeMap<XWitnessId, WitnessOrd>, String> {
+    let writer = history
+        .strict_encode(StrictWriter::in_memory::<U32>())
+        .map_err(|e| e.to_string())?;
+    let data = writer.unbox().unconfine();
+    let mut reader = StrictReader::in_memory::<U32>(data);
+    SchemaId::strict_decode(&mut reader).map_err(|e| e.to_string())?;
+    ContractId::strict_decode(&mut reader).map_err(|e| e.to_string())?;
+    let global =
+        TinyOrdMap::<GlobalStateType, LargeOrdMap<GlobalOrd, DataState>>::strict_decode(
+            &mut reader,
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(global
+        .values()
+        .flat_map(|state| state.keys())
+        .filter_map(|ord| ord.witness_anchor)
+        .map(|anchor| (anchor.witness_id, anchor.witness_ord))
+        .collect())
+}
+
+/// Changes in the mining status of witness transactions detected during
+/// [`WitnessStatus::sync`].
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct SyncReport {
+    /// Witnesses which got mined or were re-mined in a different block.
+    pub confirmed: BTreeMap<Txid, WitnessPos>,
+    /// Witnesses which were mined before, but are not mined anymore due to a
+    /// re-org; allocations created by them are not valid until the witness is
+    /// mined again.
+    pub reorged: BTreeSet<Txid>,
+    /// Witnesses which mining status can't be resolved.
+    pub failed: BTreeMap<Txid, String>,
+}
+
+impl SyncReport {
+    pub fn is_empty(&self) -> bool {
+        self.confirmed.is_empty() && self.reorged.is_empty() && self.failed.is_empty()
+    }
+
+    /// Selects from `outpoints` those which are allocated by the witnesses
+    /// which got confirmed.
+    pub fn confirmed_allocations<'a>(
+        &'a self,
+        outpoints: impl IntoIterator<Item = Outpoint> + 'a,
+    ) -> impl Iterator<Item = Outpoint> + 'a {
+        outpoints
+            .into_iter()
+            .filter(|outpoint| self.confirmed.contains_key(&outpoint.txid))
+    }
+
+    /// Selects from `outpoints` those which are allocated by the witnesses
+    /// which got re-orged out of the blockchain.
+    pub fn invalid_allocations<'a>(
+        &'a self,
+        outpoints: impl IntoIterator<Item = Outpoint> + 'a,
+    ) -> impl Iterator<Item = Outpoint> + 'a {
+        outpoints
+            .into_iter()
+            .filter(|outpoint| self.reorged.contains(&outpoint.txid))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, process};
+
+    use amplify::ByteArray;
+    use rgbstd::{Genesis, GlobalState, Operation, Transition, WitnessAnchor};
+    use strict_types::encoding::StrictDumb;
+
+    use super::*;
+
+    /// Resolver answering with the mining status from the map, failing for
+    /// unknown transactions.
+    struct Chain(BTreeMap<Txid, WitnessOrd>);
+
+    impl ResolveHeight for Chain {
+        fn resolve_height(&mut self, witness_id: XWitnessId) -> Result<WitnessAnchor, String> {
+            let XWitnessId::Bitcoin(txid) = witness_id else {
+                unreachable!()
+            };
+            let witness_ord = self
+                .0
+                .get(&txid)
+                .copied()
+                .ok_or_else(|| format!("unknown {txid}"))?;
+            Ok(WitnessAnchor {
+                witness_ord,
+                witness_id,
+            })
+        }
+    }
+
+    fn txid(no: u8) -> Txid { Txid::from_byte_array([no; 32]) }
+
+    fn mined(height: u32) -> WitnessOrd {
+        WitnessOrd::OnChain(WitnessPos::new(height, 1231006505 + height as i64).unwrap())
+    }
+
+    fn status(name: &str) -> WitnessStatus {
+        let dir = env::temp_dir().join(format!("rgb-witness-{name}-{}", process::id()));
+        WitnessStatus::load(dir).unwrap()
+    }
+
+    #[test]
+    fn status_round_trip() {
+        let dir = env::temp_dir().join(format!("rgb-witness-status-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut status = WitnessStatus::load(&dir).unwrap();
+        status.known.insert(txid(1), mined(100));
+        status.known.insert(txid(2), WitnessOrd::OffChain);
+        status.abandon(txid(3));
+        assert_eq!(
+            status.to_string(),
+            format!(
+                "{} 100 1231006605\n{} -\n{} abandoned\n",
+                txid(1),
+                txid(2),
+                txid(3)
+            )
+        );
+
+        status.store().unwrap();
+        assert_eq!(WitnessStatus::load(&dir).unwrap(), status);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_status() {
+        assert_eq!(parse_line(1, "00 100"), Err(WitnessStatusError(1)));
+        assert_eq!(parse_line(2, &format!("{} 100", txid(1))), Err(WitnessStatusError(2)));
+        assert_eq!(parse_line(3, &format!("{} 0 0", txid(1))), Err(WitnessStatusError(3)));
+    }
+
+    #[test]
+    fn sync() {
+        let mut status = status("sync");
+        status.known.insert(txid(1), mined(10));
+        status.known.insert(txid(2), mined(95));
+        status.known.insert(txid(3), WitnessOrd::OffChain);
+        status.abandon(txid(4));
+        let mut chain = Chain(bmap! {
+            txid(2) => WitnessOrd::OffChain,
+            txid(3) => mined(99),
+            txid(5) => WitnessOrd::OffChain,
+            txid(6) => WitnessOrd::OffChain,
+            txid(7) => mined(98),
+        });
+        let witnesses = [
+            // final, thus not resolved
+            (txid(1), None),
+            (txid(2), None),
+            (txid(3), None),
+            (txid(4), None),
+            // accepted as mined and re-orged out before the first sync
+            (txid(5), Some(mined(96))),
+            (txid(6), None),
+            (txid(7), None),
+            (txid(8), None),
+        ];
+        let report = status.sync(&mut chain, witnesses, 94);
+
+        assert_eq!(report.confirmed, bmap! {
+            txid(3) => WitnessPos::new(99, 1231006604).unwrap(),
+            txid(7) => WitnessPos::new(98, 1231006603).unwrap(),
+        });
+        assert_eq!(report.reorged, bset! { txid(2), txid(5) });
+        assert_eq!(report.failed, bmap! { txid(8) => format!("unknown {}", txid(8)) });
+        assert_eq!(status.status(txid(1)), Some(mined(10)));
+        assert_eq!(status.status(txid(5)), Some(WitnessOrd::OffChain));
+        assert_eq!(status.status(txid(6)), Some(WitnessOrd::OffChain));
+        assert_eq!(status.status(txid(4)), None);
+        assert_eq!(status.status(txid(8)), None);
+
+        let outpoints = (1..=8).map(|no| Outpoint::new(txid(no), 0)).collect::<Vec<_>>();
+        assert_eq!(report.confirmed_allocations(outpoints.clone()).collect::<Vec<_>>(), vec![
+            outpoints[2],
+            outpoints[6]
+        ]);
+        assert_eq!(report.invalid_allocations(outpoints.clone()).collect::<Vec<_>>(), vec![
+            outpoints[1],
+            outpoints[4]
+        ]);
+
+        // Nothing changes on the next sync
+        assert!(status.sync(&mut chain, [(txid(5), None), (txid(7), None)], 94).is_empty());
+    }
+
+    #[test]
+    fn recorded_ords() {
+        let genesis = Genesis::strict_dumb();
+        let contract_id = genesis.contract_id();
+        let mut history = ContractHistory::with(genesis.schema_id, contract_id, &genesis);
+        let mut globals = GlobalState::default();
+        globals
+            .add_state(GlobalStateType::with(1), DataState::strict_dumb())
+            .unwrap();
+        let transition = Transition {
+            contract_id,
+            globals,
+            ..Transition::strict_dumb()
+        };
+        let witness_id = XWitnessId::Bitcoin(txid(1));
+        history.add_transition(&transition, WitnessAnchor {
+            witness_ord: mined(100),
+            witness_id,
+        });
+        // Transitions without global state have no ordering recorded
+        history.add_transition(&Transition::strict_dumb(), WitnessAnchor {
+            witness_ord: mined(101),
+            witness_id: XWitnessId::Bitcoin(txid(2)),
+        });
+
+        assert_eq!(recorded_witness_ords(&history), Ok(bmap! { witness_id => mined(100) }));
+    }
+}